@@ -0,0 +1,63 @@
+#![feature(test)]
+extern crate test;
+
+use libcamilla::filters::{
+    blocks::{atomic::AtomicBBFBlock, blanket::BlanketBBFBlock, BBFBlock},
+    bloom::BBFilter,
+};
+use libcamilla::structures::sequence::{
+    complement::{Forward, Identity},
+    packed::PackedSeq,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use test::Bencher;
+
+const K: usize = 31;
+const CHUNKS: usize = 8;
+const CHUNK_LEN: usize = 1 << 16;
+
+fn chunks() -> Vec<PackedSeq<u64, Forward, Identity>> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..CHUNKS)
+        .map(|_| PackedSeq::random(CHUNK_LEN, &mut rng))
+        .collect()
+}
+
+// Insert every chunk into a fresh filter, spread over `threads` threads
+fn insert<B: BBFBlock>(chunks: &[PackedSeq<u64, Forward, Identity>], threads: usize) {
+    let filter = BBFilter::<B>::new(K, CHUNKS * CHUNK_LEN, 16, 4);
+    std::thread::scope(|s| {
+        for t in 0..threads {
+            let filter = &filter;
+            s.spawn(move || {
+                for chunk in chunks.iter().skip(t).step_by(threads) {
+                    filter.insert_kmers(chunk);
+                }
+            });
+        }
+    });
+}
+
+#[bench]
+fn atomic_1_thread(b: &mut Bencher) {
+    let chunks = chunks();
+    b.iter(|| insert::<AtomicBBFBlock>(&chunks, 1));
+}
+
+#[bench]
+fn atomic_8_threads(b: &mut Bencher) {
+    let chunks = chunks();
+    b.iter(|| insert::<AtomicBBFBlock>(&chunks, 8));
+}
+
+#[bench]
+fn blanket_1_thread(b: &mut Bencher) {
+    let chunks = chunks();
+    b.iter(|| insert::<BlanketBBFBlock>(&chunks, 1));
+}
+
+#[bench]
+fn blanket_8_threads(b: &mut Bencher) {
+    let chunks = chunks();
+    b.iter(|| insert::<BlanketBBFBlock>(&chunks, 8));
+}
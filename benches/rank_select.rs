@@ -0,0 +1,104 @@
+#![feature(test)]
+extern crate test;
+
+use bitvec::{order::Lsb0, vec::BitVec};
+use libcamilla::utils::rank_select::RankSelect;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use test::{black_box, Bencher};
+
+const LEN: usize = 1 << 22;
+const QUERIES: usize = 1000;
+
+fn setup() -> (RankSelect, Vec<usize>) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let bits: BitVec<u64, Lsb0> = (0..LEN).map(|_| rng.gen_bool(0.5)).collect();
+    let queries = (0..QUERIES).map(|_| rng.gen_range(0..LEN / 2)).collect();
+    (RankSelect::new(bits), queries)
+}
+
+// Popcount of every word before the position
+fn naive_rank1(words: &[u64], i: usize) -> usize {
+    let full: usize = words[..i / 64]
+        .iter()
+        .map(|w| w.count_ones() as usize)
+        .sum();
+    full + (words[i / 64] & ((1 << (i % 64)) - 1)).count_ones() as usize
+}
+
+// Popcount words until the one holding the n-th one, then scan its bits
+fn naive_select1(words: &[u64], mut n: usize) -> usize {
+    for (i, &word) in words.iter().enumerate() {
+        let ones = word.count_ones() as usize;
+        if n < ones {
+            let mut word = word;
+            for _ in 0..n {
+                word &= word - 1;
+            }
+            return i * 64 + word.trailing_zeros() as usize;
+        }
+        n -= ones;
+    }
+    unreachable!()
+}
+
+#[bench]
+fn rank1(b: &mut Bencher) {
+    let (rs, queries) = setup();
+    b.iter(|| {
+        queries
+            .iter()
+            .map(|&i| rs.rank1(black_box(i)))
+            .sum::<usize>()
+    });
+}
+
+#[bench]
+fn rank1_naive(b: &mut Bencher) {
+    let (rs, queries) = setup();
+    b.iter(|| {
+        queries
+            .iter()
+            .map(|&i| naive_rank1(rs.words(), black_box(i)))
+            .sum::<usize>()
+    });
+}
+
+#[bench]
+fn select1(b: &mut Bencher) {
+    let (rs, queries) = setup();
+    b.iter(|| {
+        queries
+            .iter()
+            .map(|&n| rs.select1(black_box(n)).unwrap())
+            .sum::<usize>()
+    });
+}
+
+#[bench]
+fn select1_naive(b: &mut Bencher) {
+    let (rs, queries) = setup();
+    b.iter(|| {
+        queries
+            .iter()
+            .map(|&n| naive_select1(rs.words(), black_box(n)))
+            .sum::<usize>()
+    });
+}
+
+#[bench]
+fn select0(b: &mut Bencher) {
+    let (rs, queries) = setup();
+    b.iter(|| {
+        queries
+            .iter()
+            .map(|&n| rs.select0(black_box(n)).unwrap())
+            .sum::<usize>()
+    });
+}
+
+#[bench]
+fn build(b: &mut Bencher) {
+    let (rs, _) = setup();
+    let bits = rs.into_inner();
+    b.iter(|| RankSelect::new(bits.clone()));
+}
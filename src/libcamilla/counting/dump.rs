@@ -0,0 +1,267 @@
+/*
+Sorted binary dump of k-mer counts.
+
+All integers are little endian.
+    [0, 8)    magic "CAMKMC\0\0"
+    [8, 12)   format version
+    [12, 16)  k
+    [16, 24)  number of records
+followed by records of a packed canonical k-mer (u64) and its count (u32),
+in increasing k-mer order.
+ */
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::CountingError;
+
+pub const MAGIC: [u8; 8] = *b"CAMKMC\0\0";
+pub const FORMAT_VERSION: u32 = 1;
+pub const HEADER_LEN: usize = 24;
+pub const RECORD_LEN: usize = 12;
+
+// Streaming writer, records must be pushed in increasing k-mer order
+pub struct KmerDumpWriter<W: Write + Seek> {
+    writer: W,
+    k: usize,
+    len: u64,
+    last: Option<u64>,
+}
+
+impl KmerDumpWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, k: usize) -> Result<Self, CountingError> {
+        Self::new(BufWriter::new(File::create(path)?), k)
+    }
+}
+
+impl<W: Write + Seek> KmerDumpWriter<W> {
+    pub fn new(mut writer: W, k: usize) -> Result<Self, CountingError> {
+        // The record count is patched in by finish
+        writer.write_all(&header_bytes(k, 0))?;
+        Ok(Self {
+            writer,
+            k,
+            len: 0,
+            last: None,
+        })
+    }
+
+    pub fn push(&mut self, kmer: u64, count: u32) -> Result<(), CountingError> {
+        debug_assert!(self.last.is_none_or(|last| last < kmer));
+        self.writer.write_all(&kmer.to_le_bytes())?;
+        self.writer.write_all(&count.to_le_bytes())?;
+        self.len += 1;
+        self.last = Some(kmer);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, CountingError> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header_bytes(self.k, self.len))?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn header_bytes(k: usize, len: u64) -> [u8; HEADER_LEN] {
+    let mut bytes = [0; HEADER_LEN];
+    bytes[0..8].copy_from_slice(&MAGIC);
+    bytes[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes[12..16].copy_from_slice(&(k as u32).to_le_bytes());
+    bytes[16..24].copy_from_slice(&len.to_le_bytes());
+    bytes
+}
+
+// Streaming reader over the records of a dump
+pub struct KmerDumpReader<Rd: Read> {
+    reader: Rd,
+    k: usize,
+    len: u64,
+    read: u64,
+}
+
+impl KmerDumpReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, CountingError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<Rd: Read> KmerDumpReader<Rd> {
+    pub fn new(mut reader: Rd) -> Result<Self, CountingError> {
+        let mut bytes = [0; HEADER_LEN];
+        reader.read_exact(&mut bytes)?;
+        if bytes[0..8] != MAGIC {
+            return Err(CountingError::BadMagic);
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(CountingError::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            reader,
+            k: u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize,
+            len: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            read: 0,
+        })
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    // Number of records in the dump
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<Rd: Read> Iterator for KmerDumpReader<Rd> {
+    type Item = Result<(u64, u32), CountingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.read == self.len {
+            return None;
+        }
+        let mut bytes = [0; RECORD_LEN];
+        if let Err(e) = self.reader.read_exact(&mut bytes) {
+            self.read = self.len;
+            return Some(Err(e.into()));
+        }
+        self.read += 1;
+        Some(Ok((
+            u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        )))
+    }
+}
+
+// Merge sorted dumps into one, summing the counts of k-mers present in several
+pub fn merge_dumps<P, Q>(inputs: &[P], output: Q, k: usize) -> Result<u64, CountingError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let mut readers = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let reader = KmerDumpReader::open(input)?;
+        if reader.k() != k {
+            return Err(CountingError::KMismatch {
+                expected: k,
+                found: reader.k(),
+            });
+        }
+        readers.push(reader);
+    }
+
+    // Min-heap of the next record of every reader
+    let mut heap = BinaryHeap::with_capacity(readers.len());
+    for (i, reader) in readers.iter_mut().enumerate() {
+        if let Some(record) = reader.next() {
+            let (kmer, count) = record?;
+            heap.push(Reverse((kmer, count, i)));
+        }
+    }
+
+    let mut writer = KmerDumpWriter::create(output, k)?;
+    let mut pending: Option<(u64, u32)> = None;
+    while let Some(Reverse((kmer, count, i))) = heap.pop() {
+        pending = match pending {
+            Some((last, total)) if last == kmer => Some((last, total.saturating_add(count))),
+            Some((last, total)) => {
+                writer.push(last, total)?;
+                Some((kmer, count))
+            }
+            None => Some((kmer, count)),
+        };
+        if let Some(record) = readers[i].next() {
+            let (kmer, count) = record?;
+            heap.push(Reverse((kmer, count, i)));
+        }
+    }
+    if let Some((last, total)) = pending {
+        writer.push(last, total)?;
+    }
+    let len = writer.len;
+    writer.finish()?;
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn write(records: &[(u64, u32)], k: usize) -> Vec<u8> {
+        let mut writer = KmerDumpWriter::new(Cursor::new(Vec::new()), k).unwrap();
+        for &(kmer, count) in records {
+            writer.push(kmer, count).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let records = [(1, 4), (7, 1), (90, u32::MAX)];
+        let bytes = write(&records, 11);
+        assert_eq!(bytes.len(), HEADER_LEN + records.len() * RECORD_LEN);
+        let reader = KmerDumpReader::new(&bytes[..]).unwrap();
+        assert_eq!((reader.k(), reader.len()), (11, 3));
+        assert_eq!(reader.collect::<Result<Vec<_>, _>>().unwrap(), records);
+
+        let mut magic = bytes.clone();
+        magic[0] = 0;
+        assert!(matches!(
+            KmerDumpReader::new(&magic[..]),
+            Err(CountingError::BadMagic)
+        ));
+        let last = KmerDumpReader::new(&bytes[..bytes.len() - 1])
+            .unwrap()
+            .last()
+            .unwrap();
+        assert!(matches!(last, Err(CountingError::Truncated)));
+    }
+
+    #[test]
+    fn merge_sums_counts() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let paths = (0..4)
+            .map(|i| dir.join(format!("camilla-dump-{}-{}", id, i)))
+            .collect::<Vec<_>>();
+        std::fs::write(&paths[0], write(&[(1, 1), (5, 2), (9, u32::MAX)], 9)).unwrap();
+        std::fs::write(&paths[1], write(&[(2, 1), (5, 3), (9, 1)], 9)).unwrap();
+        std::fs::write(&paths[2], write(&[(1, 1)], 8)).unwrap();
+
+        let merged = merge_dumps(&paths[..2], &paths[3], 9);
+        let mismatch = merge_dumps(&paths[..3], &paths[3], 9);
+        let records = KmerDumpReader::open(&paths[3])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert_eq!(merged.unwrap(), 4);
+        assert_eq!(records, [(1, 1), (2, 1), (5, 5), (9, u32::MAX)]);
+        assert!(matches!(
+            mismatch,
+            Err(CountingError::KMismatch {
+                expected: 9,
+                found: 8
+            })
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+use std::{error::Error, fmt::Display, io};
+
+pub mod dump;
+pub mod partition;
+pub mod spectrum;
+pub mod superkmer;
+pub mod table;
+
+#[derive(Debug)]
+pub enum CountingError {
+    Io(io::Error),
+    // No free slot is left for a new k-mer
+    TableFull,
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    // Dumps of different k cannot be merged
+    KMismatch { expected: usize, found: usize },
+}
+
+impl Display for CountingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CountingError::Io(e) => write!(f, "I/O error: {}", e),
+            CountingError::TableFull => write!(f, "K-mer table is full"),
+            CountingError::BadMagic => write!(f, "Not a Camilla k-mer dump"),
+            CountingError::UnsupportedVersion(v) => write!(f, "Unsupported dump version {}", v),
+            CountingError::Truncated => write!(f, "K-mer dump is truncated"),
+            CountingError::KMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Expected a dump of {}-mers, found {}-mers",
+                    expected, found
+                )
+            }
+        }
+    }
+}
+
+impl Error for CountingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CountingError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CountingError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            CountingError::Truncated
+        } else {
+            CountingError::Io(e)
+        }
+    }
+}
@@ -0,0 +1,264 @@
+/*
+Out-of-core k-mer counting.
+
+Sequences are split into super-k-mers, which are appended to one of
+bin_count temporary files by the hash of their minimizer. Every canonical
+k-mer lives in exactly one bin, so bins are counted one at a time within the
+memory budget and their sorted dumps merged into the final output.
+
+Bin records, all integers little endian:
+    [0, 4)    number of bases
+followed by the bases packed four per byte, first base in the high bits.
+ */
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::structures::sequence::{
+    complement::{Complementation, Forward, Identity, Reversal},
+    packed::PackedSeq,
+    storage::Storage,
+};
+
+use super::{dump::merge_dumps, table::KmerTable, CountingError};
+
+pub const DEFAULT_MINIMIZER_LENGTH: usize = 9;
+pub const DEFAULT_BIN_COUNT: usize = 128;
+pub const DEFAULT_MEMORY_BYTES: usize = 1 << 30;
+
+// Distinguishes the working directories of counters within a process
+static COUNTER_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskCountParams {
+    pub k: usize,
+    pub minimizer_length: usize,
+    pub bin_count: usize,
+    // Memory available for counting a single bin
+    pub memory_bytes: usize,
+    // Directory under which temporary bins are created
+    pub temp_dir: PathBuf,
+}
+
+impl DiskCountParams {
+    pub fn new<P: AsRef<Path>>(k: usize, temp_dir: P) -> Self {
+        Self {
+            k,
+            minimizer_length: DEFAULT_MINIMIZER_LENGTH.min(k),
+            bin_count: DEFAULT_BIN_COUNT,
+            memory_bytes: DEFAULT_MEMORY_BYTES,
+            temp_dir: temp_dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+struct Bin {
+    writer: BufWriter<File>,
+    // Number of k-mers written, an upper bound on the distinct ones
+    kmers: usize,
+}
+
+// Disk partitioned k-mer counter
+// Sequences may be inserted concurrently, each bin is guarded by its own lock.
+pub struct DiskCounter {
+    params: DiskCountParams,
+    dir: PathBuf,
+    bins: Vec<Mutex<Bin>>,
+}
+
+impl DiskCounter {
+    pub fn new(params: DiskCountParams) -> Result<Self, CountingError> {
+        assert!(params.bin_count > 0, "Bin count must be positive");
+        assert!(
+            params.minimizer_length > 0 && params.minimizer_length <= params.k,
+            "Minimizer length must be between 1 and k"
+        );
+        let dir = params.temp_dir.join(format!(
+            "camilla-kmc-{}-{}",
+            std::process::id(),
+            COUNTER_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+
+        let mut counter = Self {
+            params,
+            dir,
+            bins: Vec::new(),
+        };
+        for i in 0..counter.params.bin_count {
+            let writer = BufWriter::new(File::create(counter.bin_path(i))?);
+            counter.bins.push(Mutex::new(Bin { writer, kmers: 0 }));
+        }
+        Ok(counter)
+    }
+
+    #[inline]
+    pub fn params(&self) -> &DiskCountParams {
+        &self.params
+    }
+
+    fn bin_path(&self, i: usize) -> PathBuf {
+        self.dir.join(format!("bin{}", i))
+    }
+
+    // Partition the super-k-mers of a sequence into bins
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>) -> Result<(), CountingError>
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        let k = self.params.k;
+        let mut packed = Vec::new();
+        for superkmer in seq.super_kmer_iter(k, self.params.minimizer_length) {
+            packed.clear();
+            packed.resize(superkmer.len.div_ceil(u8::CAPACITY), 0u8);
+            for i in 0..superkmer.len {
+                let (slot, pos) = u8::addr(i);
+                packed[slot].write(pos, seq.read(superkmer.start + i).unwrap());
+            }
+
+            let bin = (superkmer.minimizer % self.bins.len() as u64) as usize;
+            let mut bin = self.bins[bin].lock().unwrap();
+            bin.writer
+                .write_all(&(superkmer.len as u32).to_le_bytes())?;
+            bin.writer.write_all(&packed)?;
+            bin.kmers += superkmer.len - k + 1;
+        }
+        Ok(())
+    }
+
+    // Count every bin and merge the counts into a sorted dump at `output`
+    // Returns the number of distinct k-mers.
+    pub fn finish<P: AsRef<Path>>(self, output: P) -> Result<u64, CountingError> {
+        let mut max_kmers = 0;
+        for bin in self.bins.iter() {
+            let mut bin = bin.lock().unwrap();
+            bin.writer.flush()?;
+            max_kmers = max_kmers.max(bin.kmers);
+        }
+
+        // The budget also covers sorting the table when it is dumped
+        let capacity = KmerTable::sortable_capacity_in(self.params.memory_bytes);
+        let mut table = KmerTable::with_capacity(self.params.k, max_kmers.min(capacity).max(1));
+
+        let mut dumps = Vec::with_capacity(self.bins.len());
+        for i in 0..self.bins.len() {
+            dumps.push(self.count_bin(i, &mut table)?);
+        }
+
+        merge_dumps(&dumps, output, self.params.k)
+    }
+
+    // Count one bin into a sorted dump, spilling sorted runs whenever the table fills up
+    fn count_bin(&self, i: usize, table: &mut KmerTable) -> Result<PathBuf, CountingError> {
+        let k = self.params.k;
+        let path = self.bin_path(i);
+        let mut reader = BufReader::new(File::open(&path)?);
+        let mut runs = Vec::new();
+        let mut len = [0; 4];
+        loop {
+            match reader.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            let len = u32::from_le_bytes(len) as usize;
+            let mut packed = vec![0u8; len.div_ceil(u8::CAPACITY)];
+            reader.read_exact(&mut packed)?;
+
+            let superkmer = PackedSeq::<u8, Forward, Identity>::from_storage(packed, len);
+            for kmer in superkmer.canonical_kmer_iter(k) {
+                match table.add(kmer, 1) {
+                    Err(CountingError::TableFull) => {
+                        let run = self.dir.join(format!("bin{}.run{}", i, runs.len()));
+                        table.dump(&run)?;
+                        runs.push(run);
+                        table.clear();
+                        table.add(kmer, 1)?;
+                    }
+                    res => {
+                        res?;
+                    }
+                }
+            }
+        }
+        drop(reader);
+        fs::remove_file(&path)?;
+
+        let dump = self.dir.join(format!("bin{}.kmc", i));
+        if runs.is_empty() {
+            table.dump(&dump)?;
+        } else {
+            let run = self.dir.join(format!("bin{}.run{}", i, runs.len()));
+            table.dump(&run)?;
+            runs.push(run);
+            merge_dumps(&runs, &dump, k)?;
+            for run in runs {
+                fs::remove_file(run)?;
+            }
+        }
+        table.clear();
+        Ok(dump)
+    }
+}
+
+impl Drop for DiskCounter {
+    fn drop(&mut self) {
+        // Best effort, the directory only holds intermediate files
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::counting::dump::KmerDumpReader;
+
+    type Seq = PackedSeq<u64, Forward, Identity>;
+
+    fn read_dump(path: &Path) -> Vec<(u64, u32)> {
+        KmerDumpReader::open(path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn matches_in_memory_table() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let k = 15;
+        // Repeated sequences, so counts above one cross table spills
+        let seqs = (0..4)
+            .map(|_| Seq::random(10_000, &mut rng))
+            .collect::<Vec<_>>();
+        let table = KmerTable::with_capacity(k, 100_000);
+        let mut params = DiskCountParams::new(k, std::env::temp_dir());
+        params.bin_count = 4;
+        // A few hundred k-mers per table, against thousands per bin
+        params.memory_bytes = 8 << 10;
+        let counter = DiskCounter::new(params).unwrap();
+        for (i, seq) in seqs.iter().enumerate() {
+            for _ in 0..=i {
+                table.insert_kmers(seq).unwrap();
+                counter.insert_kmers(seq).unwrap();
+            }
+        }
+        assert!(KmerTable::sortable_capacity_in(8 << 10) < table.len() / 20);
+
+        let output = std::env::temp_dir().join(format!("camilla-kmc-{}.kmc", std::process::id()));
+        let distinct = counter.finish(&output).unwrap();
+        let counted = read_dump(&output);
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(distinct as usize, table.len());
+        assert_eq!(counted, table.sorted());
+    }
+}
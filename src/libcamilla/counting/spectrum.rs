@@ -0,0 +1,364 @@
+/*
+K-mer abundance spectrum and a first peak genome model.
+
+The model follows the peak analysis of GenomeScope without fitting its
+negative binomial mixture. Error k-mers form the low abundance tail that
+ends at the first trough. In a diploid genome heterozygous k-mers form a
+peak at half the depth of homozygous ones, and the depth of the homozygous
+peak converts the number of solid k-mer occurrences into a haploid size.
+ */
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use super::{dump::KmerDumpReader, table::KmerTable, CountingError};
+
+// Number of distinct k-mers seen exactly i times, at index i
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KmerSpectrum {
+    counts: Vec<u64>,
+}
+
+// Secondary peaks lower than this fraction of the main peak are ignored
+const MIN_PEAK_RATIO: f64 = 0.1;
+
+impl KmerSpectrum {
+    pub fn from_histogram(mut counts: Vec<u64>) -> Self {
+        while counts.last() == Some(&0) {
+            counts.pop();
+        }
+        Self { counts }
+    }
+
+    pub fn from_table(table: &KmerTable) -> Self {
+        Self::from_histogram(table.histogram())
+    }
+
+    pub fn from_dump<P: AsRef<Path>>(path: P) -> Result<Self, CountingError> {
+        let mut counts = Vec::new();
+        for record in KmerDumpReader::open(path)? {
+            let count = record?.1 as usize;
+            if counts.len() <= count {
+                counts.resize(count + 1, 0);
+            }
+            counts[count] += 1;
+        }
+        Ok(Self::from_histogram(counts))
+    }
+
+    #[inline]
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    // Number of distinct k-mers seen exactly `abundance` times
+    #[inline]
+    pub fn get(&self, abundance: usize) -> u64 {
+        self.counts.get(abundance).copied().unwrap_or(0)
+    }
+
+    // Largest abundance seen
+    #[inline]
+    pub fn max_abundance(&self) -> usize {
+        self.counts.len().saturating_sub(1)
+    }
+
+    pub fn distinct(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Number of k-mer occurrences
+    pub fn total(&self) -> u64 {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, n)| i as u64 * n)
+            .sum()
+    }
+
+    // Tab separated abundance and number of k-mers, one line per nonzero abundance
+    pub fn write_tsv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (i, n) in self.counts.iter().enumerate().skip(1) {
+            if *n > 0 {
+                writeln!(writer, "{}\t{}", i, n)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Parse the output of write_tsv, or any histogram of whitespace separated abundance and count
+    // lines such as those of jellyfish histo. Abundances may repeat and come in any order.
+    pub fn read_tsv<R: BufRead>(reader: R) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid histogram line: {}", line),
+            )
+        };
+        let mut counts = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+            let (abundance, n) = match (fields.next(), fields.next(), fields.next()) {
+                (None, _, _) => continue,
+                (Some(abundance), Some(n), None) => (abundance, n),
+                _ => return Err(invalid(&line)),
+            };
+            let abundance: usize = abundance.parse().map_err(|_| invalid(&line))?;
+            let n: u64 = n.parse().map_err(|_| invalid(&line))?;
+            if counts.len() <= abundance {
+                counts.resize(abundance + 1, 0);
+            }
+            counts[abundance] += n;
+        }
+        Ok(Self::from_histogram(counts))
+    }
+
+    // Spectrum smoothed by a three point moving average
+    fn smoothed(&self) -> Vec<f64> {
+        let n = self.counts.len();
+        (0..n)
+            .map(|i| {
+                if i == 0 {
+                    return 0.0;
+                }
+                let lo = (i - 1).max(1);
+                let hi = (i + 1).min(n - 1);
+                (lo..=hi).map(|j| self.counts[j] as f64).sum::<f64>() / (hi - lo + 1) as f64
+            })
+            .collect()
+    }
+
+    // Abundance of the first local minimum, where error k-mers stop dominating
+    pub fn trough(&self) -> Option<usize> {
+        let smooth = self.smoothed();
+        (2..smooth.len().saturating_sub(1)).find(|&i| smooth[i] <= smooth[i + 1])
+    }
+
+    // Fit the first peak model, None if no peak stands out of the error tail
+    pub fn fit(&self, k: usize) -> Option<GenomeModel> {
+        let trough = self.trough()?;
+        let smooth = self.smoothed();
+        let peak = (trough..smooth.len()).max_by(|&a, &b| smooth[a].total_cmp(&smooth[b]))?;
+        if peak <= trough {
+            return None;
+        }
+
+        let local_max = |lo: usize, hi: usize| -> Option<usize> {
+            (lo.max(trough)..hi.min(smooth.len()))
+                .max_by(|&a, &b| smooth[a].total_cmp(&smooth[b]))
+                .filter(|&i| {
+                    i > trough
+                        && i + 1 < smooth.len()
+                        && smooth[i] >= smooth[i - 1]
+                        && smooth[i] >= smooth[i + 1]
+                        && smooth[i] >= MIN_PEAK_RATIO * smooth[peak]
+                })
+        };
+
+        // The highest peak is homozygous unless a second peak sits at twice its depth
+        let (het_peak, hom_peak) = if let Some(hom) = local_max(peak * 7 / 4, peak * 9 / 4 + 1) {
+            (Some(peak), hom)
+        } else if let Some(het) = local_max(peak * 3 / 8, peak * 5 / 8 + 1) {
+            (Some(het), peak)
+        } else {
+            (None, peak)
+        };
+
+        let kmer_coverage = refine(&smooth, hom_peak);
+        let het_coverage = het_peak.map(|p| refine(&smooth, p));
+
+        let total = self.total() as f64;
+        let errors: u64 = (1..trough).map(|i| i as u64 * self.get(i)).sum();
+        let solid = total - errors as f64;
+        let genome_size = solid / kmer_coverage;
+
+        // Distinct k-mers between the trough and the midpoint of both peaks are heterozygous
+        let heterozygosity = match het_coverage {
+            Some(het) => {
+                let split = ((het + kmer_coverage) / 2.0).round() as usize;
+                let het_kmers: u64 = (trough..split).map(|i| self.get(i)).sum();
+                let hom_kmers: u64 = (split..self.counts.len()).map(|i| self.get(i)).sum();
+                // Each allele contributes its own k-mers, so haplotype pairs halve the count
+                let pairs = het_kmers as f64 / 2.0;
+                let per_kmer = pairs / (pairs + hom_kmers as f64);
+                1.0 - (1.0 - per_kmer).powf(1.0 / k as f64)
+            }
+            None => 0.0,
+        };
+
+        Some(GenomeModel {
+            k,
+            min_abundance: trough,
+            kmer_coverage,
+            het_coverage,
+            genome_size,
+            heterozygosity,
+            error_fraction: errors as f64 / total,
+        })
+    }
+}
+
+// Peak position refined by a parabola through its neighbours
+fn refine(smooth: &[f64], peak: usize) -> f64 {
+    if peak == 0 || peak + 1 >= smooth.len() {
+        return peak as f64;
+    }
+    let (a, b, c) = (smooth[peak - 1], smooth[peak], smooth[peak + 1]);
+    let denom = a - 2.0 * b + c;
+    if denom == 0.0 {
+        peak as f64
+    } else {
+        peak as f64 + 0.5 * (a - c) / denom
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenomeModel {
+    pub k: usize,
+    // First trough of the spectrum, k-mers seen less often are likely errors
+    pub min_abundance: usize,
+    // Depth of the homozygous peak
+    pub kmer_coverage: f64,
+    // Depth of the heterozygous peak, if one was found
+    pub het_coverage: Option<f64>,
+    // Haploid genome size in bases
+    pub genome_size: f64,
+    // Fraction of heterozygous bases
+    pub heterozygosity: f64,
+    // Fraction of k-mer occurrences attributed to sequencing errors
+    pub error_fraction: f64,
+}
+
+impl GenomeModel {
+    // Per base coverage of reads of the given length
+    pub fn base_coverage(&self, read_len: usize) -> f64 {
+        assert!(read_len >= self.k, "Reads must be at least k long");
+        self.kmer_coverage * read_len as f64 / (read_len - self.k + 1) as f64
+    }
+
+    // Per base error rate, assuming every error k-mer comes from a single error
+    pub fn error_rate(&self) -> f64 {
+        1.0 - (1.0 - self.error_fraction).powf(1.0 / self.k as f64)
+    }
+
+    // Smallest k for which a random k-mer occurs in the genome with probability below `collision`
+    pub fn suggest_k(&self, collision: f64) -> usize {
+        suggest_k(self.genome_size, collision)
+    }
+}
+
+pub fn suggest_k(genome_size: f64, collision: f64) -> usize {
+    assert!(
+        collision > 0.0 && collision < 1.0,
+        "Collision probability must be in (0, 1)"
+    );
+    (genome_size * (1.0 - collision) / collision)
+        .log(4.0)
+        .ceil()
+        .max(1.0) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K: usize = 21;
+    const GENOME: f64 = 2e6;
+
+    // Expected number of k-mers at each abundance up to `len`, with depth `lambda`
+    fn poisson(n: f64, lambda: f64, len: usize) -> Vec<f64> {
+        let mut p = (-lambda).exp();
+        (0..len)
+            .map(|i| {
+                let x = n * p;
+                p *= lambda / (i + 1) as f64;
+                x
+            })
+            .collect()
+    }
+
+    // Errors as a geometric tail of single occurrence k-mers, plus the given peaks
+    fn spectrum(peaks: &[(f64, f64)]) -> KmerSpectrum {
+        let len = 200;
+        let mut counts = (0..len)
+            .map(|i| {
+                if i == 0 {
+                    0.0
+                } else {
+                    4e6 * 0.3f64.powi(i as i32 - 1)
+                }
+            })
+            .collect::<Vec<_>>();
+        for &(n, lambda) in peaks {
+            for (c, x) in counts.iter_mut().zip(poisson(n, lambda, len)) {
+                *c += x;
+            }
+        }
+        counts[0] = 0.0;
+        KmerSpectrum::from_histogram(counts.into_iter().map(|x| x.round() as u64).collect())
+    }
+
+    fn within(value: f64, expected: f64, tolerance: f64) -> bool {
+        (value - expected).abs() <= tolerance * expected
+    }
+
+    #[test]
+    fn haploid() {
+        let model = spectrum(&[(GENOME, 30.0)]).fit(K).unwrap();
+        assert!(within(model.kmer_coverage, 30.0, 0.03), "{:?}", model);
+        assert!(within(model.genome_size, GENOME, 0.05), "{:?}", model);
+        assert_eq!(model.het_coverage, None);
+        assert_eq!(model.heterozygosity, 0.0);
+        assert!(model.min_abundance > 2 && model.min_abundance < 15);
+        assert!(model.error_fraction > 0.0 && model.error_fraction < 0.15);
+    }
+
+    #[test]
+    fn diploid() {
+        for het in [0.005, 0.01] {
+            // Heterozygous k-mers come in allele pairs at half the homozygous depth
+            let per_kmer = 1.0 - (1.0f64 - het).powi(K as i32);
+            let model = spectrum(&[
+                (GENOME * (1.0 - per_kmer), 40.0),
+                (2.0 * GENOME * per_kmer, 20.0),
+            ])
+            .fit(K)
+            .unwrap();
+            assert!(within(model.kmer_coverage, 40.0, 0.03), "{:?}", model);
+            assert!(
+                within(model.het_coverage.unwrap(), 20.0, 0.05),
+                "{:?}",
+                model
+            );
+            assert!(within(model.genome_size, GENOME, 0.05), "{:?}", model);
+            // The homozygous tail below the split passes for heterozygous k-mers
+            assert!(model.heterozygosity >= het, "{:?}", model);
+            assert!(within(model.heterozygosity, het, 0.25), "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn no_peak() {
+        assert_eq!(KmerSpectrum::default().fit(K), None);
+        assert_eq!(KmerSpectrum::from_histogram(vec![0, 0, 0]).fit(K), None);
+        assert_eq!(spectrum(&[]).fit(K), None);
+    }
+
+    #[test]
+    fn tsv_round_trip() {
+        let spectrum = spectrum(&[(GENOME, 30.0)]);
+        let mut tsv = Vec::new();
+        spectrum.write_tsv(&mut tsv).unwrap();
+        assert_eq!(KmerSpectrum::read_tsv(&tsv[..]).unwrap(), spectrum);
+
+        let histo = "3 10\n1 5\n\n3 2\n";
+        assert_eq!(
+            KmerSpectrum::read_tsv(histo.as_bytes()).unwrap().counts(),
+            [0, 5, 0, 12]
+        );
+        assert!(KmerSpectrum::read_tsv("1\t2\t3\n".as_bytes()).is_err());
+        assert!(KmerSpectrum::read_tsv("x 2\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+/*
+Super-k-mers are maximal runs of consecutive k-mers sharing a minimizer.
+Minimizers are taken over canonical m-mers, so a k-mer and its reverse
+complement always share one and land in the same partition.
+ */
+use std::collections::VecDeque;
+
+use crate::structures::sequence::{
+    complement::{Complementation, Reversal},
+    kmer::{complement_bits, kmer_mask},
+    packed::{PackedSeq, PackedSeqSlice},
+    storage::Storage,
+};
+use crate::utils::hash::mix64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuperKmer {
+    // Hash of the shared canonical minimizer
+    pub minimizer: u64,
+    // Position of the first base in the sequence
+    pub start: usize,
+    // Number of bases, at least k
+    pub len: usize,
+}
+
+// Iterator over the super-k-mers of a sequence slice
+#[derive(Debug)]
+pub struct SuperKmerIter<'a, T: Storage, R: Reversal, C: Complementation> {
+    data: PackedSeqSlice<'a, T, R, C>,
+    pos: usize,
+    k: usize,
+    m: usize,
+    forward: u64,
+    reverse: u64,
+    mask: u64,
+    // Monotone queue of (start, hash) for the m-mers in the current window
+    window: VecDeque<(usize, u64)>,
+    // Super-k-mer being extended
+    current: Option<SuperKmer>,
+}
+
+impl<'a, T: Storage, R: Reversal, C: Complementation> SuperKmerIter<'a, T, R, C> {
+    pub fn new(data: PackedSeqSlice<'a, T, R, C>, k: usize, m: usize) -> Self {
+        assert!(
+            m > 0 && m <= k && m < 32,
+            "Minimizer length must be between 1 and min(k, 31)"
+        );
+        Self {
+            data,
+            pos: 0,
+            k,
+            m,
+            forward: 0,
+            reverse: 0,
+            mask: kmer_mask(m),
+            window: VecDeque::with_capacity(k - m + 1),
+            current: None,
+        }
+    }
+}
+
+impl<'a, T: Storage, R: Reversal, C: Complementation> Iterator for SuperKmerIter<'a, T, R, C> {
+    type Item = SuperKmer;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.data.len {
+            let x = self.data.get(self.pos) as u64;
+            self.forward = ((self.forward << 2) | x) & self.mask;
+            self.reverse = (self.reverse >> 2) | (complement_bits(x) << (2 * (self.m - 1)));
+            self.pos += 1;
+
+            if self.pos < self.m {
+                continue;
+            }
+
+            let start = self.pos - self.m;
+            let hash = mix64(self.forward.min(self.reverse));
+            while self.window.back().is_some_and(|&(_, h)| h > hash) {
+                self.window.pop_back();
+            }
+            self.window.push_back((start, hash));
+
+            if self.pos < self.k {
+                continue;
+            }
+
+            let kmer_start = self.pos - self.k;
+            while self.window.front().is_some_and(|&(s, _)| s < kmer_start) {
+                self.window.pop_front();
+            }
+            let minimizer = self.window.front().unwrap().1;
+
+            match &mut self.current {
+                Some(current) if current.minimizer == minimizer => current.len += 1,
+                current => {
+                    let done = current.replace(SuperKmer {
+                        minimizer,
+                        start: kmer_start,
+                        len: self.k,
+                    });
+                    if done.is_some() {
+                        return done;
+                    }
+                }
+            }
+        }
+        self.current.take()
+    }
+}
+
+impl<T, R, C> PackedSeq<T, R, C>
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    pub fn super_kmer_iter(&self, k: usize, m: usize) -> SuperKmerIter<'_, T, R, C> {
+        SuperKmerIter::new(self.as_slice(), k, m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::{
+        complement::{Forward, Identity},
+        kmer::{canonical, pack_kmer, reverse_complement},
+    };
+
+    const K: usize = 21;
+    const M: usize = 7;
+
+    fn random(seed: u64) -> PackedSeq<u64, Forward, Identity> {
+        PackedSeq::random(5_000, &mut StdRng::seed_from_u64(seed))
+    }
+
+    // Minimizer of the k-mer at `start`, from scratch
+    fn naive_minimizer<R: Reversal, C: Complementation>(
+        seq: &PackedSeq<u64, R, C>,
+        start: usize,
+    ) -> u64 {
+        (start..=start + K - M)
+            .map(|i| {
+                let mmer = pack_kmer(&seq.slice(i, M));
+                mix64(mmer.min(reverse_complement(mmer, M)))
+            })
+            .min()
+            .unwrap()
+    }
+
+    // Canonical k-mer to minimizer, for every super-k-mer of the sequence
+    fn partition<R: Reversal, C: Complementation>(seq: &PackedSeq<u64, R, C>) -> HashMap<u64, u64> {
+        let mut next = 0;
+        let mut bins = HashMap::new();
+        for superkmer in seq.super_kmer_iter(K, M) {
+            // Super-k-mers tile the k-mers of the sequence, consecutive ones overlapping by k - 1
+            assert_eq!(superkmer.start, next);
+            assert!(superkmer.len >= K);
+            next = superkmer.start + superkmer.len - K + 1;
+            for start in next - (superkmer.len - K + 1)..next {
+                assert_eq!(naive_minimizer(seq, start), superkmer.minimizer);
+                let kmer = canonical(pack_kmer(&seq.slice(start, K)), K);
+                bins.insert(kmer, superkmer.minimizer);
+            }
+        }
+        assert_eq!(next, seq.len() - K + 1);
+        bins
+    }
+
+    #[test]
+    fn every_kmer_in_one_superkmer() {
+        let seq = random(0);
+        let forward = partition(&seq);
+        // Both strands of a k-mer share its minimizer, so land in the same bin
+        let reverse = partition(&random(0).reverse_complement());
+        assert_eq!(forward, reverse);
+        // Super-k-mers average well over one k-mer
+        assert!(seq.super_kmer_iter(K, M).count() * 4 < seq.len());
+    }
+
+    #[test]
+    fn short_sequences() {
+        let seq = random(1);
+        let exact = PackedSeq::<u64, Forward, Identity>::from_storage(seq.storage().to_vec(), K);
+        assert_eq!(
+            exact.super_kmer_iter(K, M).collect::<Vec<_>>(),
+            [SuperKmer {
+                minimizer: naive_minimizer(&exact, 0),
+                start: 0,
+                len: K
+            }]
+        );
+        let short =
+            PackedSeq::<u64, Forward, Identity>::from_storage(seq.storage().to_vec(), K - 1);
+        assert_eq!(short.super_kmer_iter(K, M).count(), 0);
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    mem::size_of,
+    path::Path,
+    sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::structures::{
+    collection::Collection,
+    sequence::{
+        complement::{Complementation, Reversal},
+        kmer::{canonical, pack_kmer, MAX_K},
+        packed::{PackedSeq, PackedSeqSlice},
+        storage::Storage,
+    },
+};
+use crate::utils::hash::mix64;
+
+use super::{dump::KmerDumpWriter, CountingError};
+
+// All ones is the packed k-mer of 32 As, so k is limited to 31
+const EMPTY: u64 = u64::MAX;
+const MAX_LOAD: f64 = 0.9;
+
+// Concurrent open addressing table of exact canonical k-mer counts
+// Slots are claimed by compare and swap and probed linearly.
+// The table does not grow, insertion fails once MAX_LOAD is reached.
+pub struct KmerTable {
+    keys: Vec<AtomicU64>,
+    counts: Vec<AtomicU32>,
+    k: usize,
+    mask: usize,
+    len: AtomicUsize,
+    max_len: usize,
+}
+
+impl KmerTable {
+    // Bytes of memory per slot, a key and a count
+    pub const SLOT_BYTES: usize = 12;
+
+    // Table able to hold `capacity` distinct k-mers
+    pub fn with_capacity(k: usize, capacity: usize) -> Self {
+        assert!(k > 0 && k < MAX_K, "k must be between 1 and {}", MAX_K - 1);
+        let slots = ((capacity as f64 / MAX_LOAD).ceil() as usize)
+            .max(2)
+            .next_power_of_two();
+        Self {
+            keys: (0..slots).map(|_| AtomicU64::new(EMPTY)).collect(),
+            counts: (0..slots).map(|_| AtomicU32::new(0)).collect(),
+            k,
+            mask: slots - 1,
+            len: AtomicUsize::new(0),
+            max_len: (slots as f64 * MAX_LOAD) as usize,
+        }
+    }
+
+    // Largest table fitting in `bytes` of memory
+    pub fn with_memory(k: usize, bytes: usize) -> Self {
+        Self::with_capacity(k, Self::capacity_in(bytes))
+    }
+
+    // Distinct k-mers held by the largest table fitting in `bytes` of memory
+    pub fn capacity_in(bytes: usize) -> usize {
+        let slots = (bytes / Self::SLOT_BYTES).max(2);
+        // Round down to a power of two
+        let slots = 1usize << (usize::BITS - 1 - slots.leading_zeros());
+        (slots as f64 * MAX_LOAD) as usize
+    }
+
+    // Distinct k-mers held by the largest table fitting in `bytes` along with its sorted pairs
+    pub fn sortable_capacity_in(bytes: usize) -> usize {
+        // A full table sorts into MAX_LOAD pairs per slot
+        let per_slot = Self::SLOT_BYTES as f64 + MAX_LOAD * size_of::<(u64, u32)>() as f64;
+        Self::capacity_in((bytes as f64 / per_slot) as usize * Self::SLOT_BYTES)
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    // Number of distinct k-mers
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.keys.len() * Self::SLOT_BYTES
+    }
+
+    // Add `count` occurrences of a packed canonical k-mer, returning its previous count
+    // Counts saturate at u32::MAX.
+    pub fn add(&self, kmer: u64, count: u32) -> Result<u32, CountingError> {
+        let mut slot = mix64(kmer) as usize & self.mask;
+        loop {
+            let key = self.keys[slot].load(Ordering::Acquire);
+            if key == kmer {
+                return Ok(self.add_count(slot, count));
+            }
+            if key == EMPTY {
+                if self.len.fetch_add(1, Ordering::Relaxed) >= self.max_len {
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    return Err(CountingError::TableFull);
+                }
+                match self.keys[slot].compare_exchange(
+                    EMPTY,
+                    kmer,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => return Ok(self.add_count(slot, count)),
+                    Err(other) => {
+                        // Lost the slot, possibly to the same k-mer
+                        self.len.fetch_sub(1, Ordering::Relaxed);
+                        if other == kmer {
+                            return Ok(self.add_count(slot, count));
+                        }
+                    }
+                }
+            }
+            slot = (slot + 1) & self.mask;
+        }
+    }
+
+    #[inline]
+    fn add_count(&self, slot: usize, count: u32) -> u32 {
+        self.counts[slot]
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| {
+                Some(c.saturating_add(count))
+            })
+            .unwrap()
+    }
+
+    pub fn get(&self, kmer: u64) -> u32 {
+        let mut slot = mix64(kmer) as usize & self.mask;
+        loop {
+            match self.keys[slot].load(Ordering::Acquire) {
+                key if key == kmer => return self.counts[slot].load(Ordering::Relaxed),
+                EMPTY => return 0,
+                _ => slot = (slot + 1) & self.mask,
+            }
+        }
+    }
+
+    // Count every canonical k-mer of a sequence
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>) -> Result<(), CountingError>
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        for kmer in seq.canonical_kmer_iter(self.k) {
+            self.add(kmer, 1)?;
+        }
+        Ok(())
+    }
+
+    // Occurrences of a k-mer or its reverse complement
+    pub fn count_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> u32
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        self.get(canonical(pack_kmer(&kmer), self.k))
+    }
+
+    // Unordered (k-mer, count) pairs
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u32)> + '_ {
+        self.keys
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(k, c)| (k.load(Ordering::Relaxed), c.load(Ordering::Relaxed)))
+            .filter(|(k, _)| *k != EMPTY)
+    }
+
+    // (k-mer, count) pairs in increasing k-mer order
+    pub fn sorted(&self) -> Vec<(u64, u32)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by_key(|(k, _)| *k);
+        entries
+    }
+
+    // Number of k-mers seen exactly i times, at index i
+    pub fn histogram(&self) -> Vec<u64> {
+        let mut histogram = Vec::new();
+        for (_, count) in self.iter() {
+            let count = count as usize;
+            if histogram.len() <= count {
+                histogram.resize(count + 1, 0);
+            }
+            histogram[count] += 1;
+        }
+        histogram
+    }
+
+    // Write every k-mer and its count to a sorted binary dump
+    pub fn dump<P: AsRef<Path>>(&self, path: P) -> Result<(), CountingError> {
+        let mut writer = KmerDumpWriter::create(path, self.k)?;
+        for (kmer, count) in self.sorted() {
+            writer.push(kmer, count)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        for key in self.keys.iter_mut() {
+            *key.get_mut() = EMPTY;
+        }
+        for count in self.counts.iter_mut() {
+            *count.get_mut() = 0;
+        }
+        *self.len.get_mut() = 0;
+    }
+}
+
+// Exact set of canonical k-mers
+impl<'a, T, R, C> Collection<PackedSeqSlice<'a, T, R, C>> for KmerTable
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    type Error = CountingError;
+
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn insert(&self, x: PackedSeqSlice<'a, T, R, C>) -> Result<bool, CountingError> {
+        debug_assert_eq!(x.len, self.k);
+        Ok(self.add(canonical(pack_kmer(&x), self.k), 1)? > 0)
+    }
+
+    fn contains(&self, x: PackedSeqSlice<'a, T, R, C>) -> bool {
+        self.count_kmer(x) > 0
+    }
+
+    fn len_estimate(&self) -> usize {
+        self.len()
+    }
+
+    fn fpr(&self) -> f64 {
+        0.0
+    }
+
+    fn canonical(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::complement::{Forward, Identity};
+
+    type Seq = PackedSeq<u64, Forward, Identity>;
+
+    // Short k, so that random k-mers repeat many times
+    const K: usize = 7;
+
+    fn random(seed: u64, len: usize) -> Seq {
+        Seq::random(len, &mut StdRng::seed_from_u64(seed))
+    }
+
+    fn exact_counts(seq: &Seq, k: usize) -> HashMap<u64, u32> {
+        let mut exact = HashMap::new();
+        for i in 0..=seq.len() - k {
+            *exact
+                .entry(canonical(pack_kmer(&seq.slice(i, k)), k))
+                .or_insert(0) += 1;
+        }
+        exact
+    }
+
+    #[test]
+    fn concurrent_inserts_match_hashmap() {
+        let seq = random(0, 100_000);
+        let table = KmerTable::with_capacity(K, 1 << 14);
+        let positions = (0..=seq.len() - K).collect::<Vec<_>>();
+        std::thread::scope(|scope| {
+            for chunk in positions.chunks(10_000) {
+                let (table, seq) = (&table, &seq);
+                scope.spawn(move || {
+                    for &i in chunk {
+                        table
+                            .add(canonical(pack_kmer(&seq.slice(i, K)), K), 1)
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        let exact = exact_counts(&seq, K);
+        assert_eq!(table.len(), exact.len());
+        assert_eq!(table.iter().collect::<HashMap<_, _>>(), exact);
+
+        let serial = KmerTable::with_capacity(K, 1 << 14);
+        serial.insert_kmers(&seq).unwrap();
+        assert_eq!(serial.sorted(), table.sorted());
+    }
+
+    #[test]
+    fn sorted_and_histogram() {
+        let seq = random(1, 50_000);
+        let table = KmerTable::with_capacity(K, 1 << 14);
+        table.insert_kmers(&seq).unwrap();
+
+        let sorted = table.sorted();
+        assert_eq!(sorted.len(), table.len());
+        assert!(sorted.windows(2).all(|w| w[0].0 < w[1].0));
+        let mut unordered = table.iter().collect::<Vec<_>>();
+        unordered.sort_unstable();
+        assert_eq!(unordered, sorted);
+
+        let histogram = table.histogram();
+        assert_eq!(histogram[0], 0);
+        assert_eq!(histogram.iter().sum::<u64>(), table.len() as u64);
+        let total = histogram
+            .iter()
+            .enumerate()
+            .map(|(i, n)| i as u64 * n)
+            .sum::<u64>();
+        assert_eq!(total, (seq.len() - K + 1) as u64);
+        let max = sorted.iter().map(|(_, c)| *c).max().unwrap() as usize;
+        assert_eq!(histogram.len(), max + 1);
+        assert!(histogram[max] > 0);
+    }
+
+    #[test]
+    fn reverse_complements_merge() {
+        let k = 21;
+        let seq = random(2, 5_000);
+        let rc = random(2, 5_000).reverse_complement();
+        let forward = KmerTable::with_capacity(k, 1 << 14);
+        let reverse = KmerTable::with_capacity(k, 1 << 14);
+        forward.insert_kmers(&seq).unwrap();
+        reverse.insert_kmers(&rc).unwrap();
+        assert_eq!(forward.sorted(), reverse.sorted());
+
+        // Both strands count towards one canonical k-mer
+        let both = KmerTable::with_capacity(k, 1 << 14);
+        both.insert_kmers(&seq).unwrap();
+        both.insert_kmers(&rc).unwrap();
+        assert_eq!(both.len(), forward.len());
+        let last = seq.len() - k;
+        for i in 0..=last {
+            let count = forward.count_kmer(seq.slice(i, k));
+            assert_eq!(reverse.count_kmer(rc.slice(last - i, k)), count);
+            assert_eq!(both.count_kmer(seq.slice(i, k)), 2 * count);
+            assert_eq!(both.count_kmer(rc.slice(last - i, k)), 2 * count);
+        }
+    }
+
+    #[test]
+    fn sorting_fits_the_budget() {
+        for bytes in [1 << 12, 100_000, 1 << 20, 3 << 20] {
+            let capacity = KmerTable::sortable_capacity_in(bytes);
+            let table = KmerTable::with_capacity(21, capacity);
+            assert_eq!(table.capacity(), capacity);
+            let sorted = capacity * size_of::<(u64, u32)>();
+            assert!(table.memory_bytes() + sorted <= bytes);
+            // Within the power of two rounding of the table
+            assert!(2 * (table.memory_bytes() + sorted) > bytes);
+        }
+    }
+
+    #[test]
+    fn counts_saturate() {
+        let table = KmerTable::with_capacity(5, 16);
+        assert_eq!(table.add(7, u32::MAX - 1).unwrap(), 0);
+        assert_eq!(table.add(7, 1).unwrap(), u32::MAX - 1);
+        assert_eq!(table.add(7, 5).unwrap(), u32::MAX);
+        assert_eq!(table.get(7), u32::MAX);
+        assert_eq!(table.add(9, 3).unwrap(), 0);
+        assert_eq!(table.get(9), 3);
+        assert_eq!(table.len(), 2);
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::filters::{BLOCK_SIZE, NUM_INTS};
+
+use super::BBFBlock;
+
+// Lock-free implementation of a BBFBlock
+// Bits are only ever set, so relaxed ordering suffices for both inserts and reads.
+pub struct AtomicBBFBlock {
+    buffer: [AtomicU32; NUM_INTS + 1],
+}
+
+impl AtomicBBFBlock {
+    // Set the bit for a hash, returning whether it was already set
+    #[inline]
+    fn set(&self, hash: usize) -> bool {
+        let bit = 1 << (31 - hash % 32);
+        self.buffer[(hash % BLOCK_SIZE) / 32].fetch_or(bit, Ordering::Relaxed) & bit != 0
+    }
+}
+
+impl Clone for AtomicBBFBlock {
+    fn clone(&self) -> Self {
+        AtomicBBFBlock {
+            buffer: std::array::from_fn(|i| AtomicU32::new(self.buffer[i].load(Ordering::Relaxed))),
+        }
+    }
+}
+
+impl Default for AtomicBBFBlock {
+    fn default() -> Self {
+        AtomicBBFBlock {
+            buffer: std::array::from_fn(|_| AtomicU32::new(0)),
+        }
+    }
+}
+
+impl BBFBlock for AtomicBBFBlock {
+    // Same bits as BlanketBBFBlock
+    const LAYOUT: u32 = 1;
+    const WORDS: usize = NUM_INTS + 1;
+
+    #[inline]
+    fn insert(&self, hash: usize) -> bool {
+        let res = self.set(hash);
+        if !res {
+            self.buffer[NUM_INTS].fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    #[inline]
+    fn insert_all(&self, hashes: &[usize]) -> bool {
+        let mut res = true;
+        for hash in hashes.iter() {
+            res &= self.set(*hash);
+        }
+        if !res {
+            self.buffer[NUM_INTS].fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    #[inline]
+    fn insert_all_unchecked(&self, hashes: &[usize]) {
+        for hash in hashes.iter() {
+            let bit = 1 << (31 - hash % 32);
+            self.buffer[(hash % BLOCK_SIZE) / 32].fetch_or(bit, Ordering::Relaxed);
+        }
+        self.buffer[NUM_INTS].fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn read(&self, hash: usize) -> bool {
+        self.buffer[(hash % BLOCK_SIZE) / 32].load(Ordering::Relaxed) >> (31 - hash % 32) & 1 != 0
+    }
+
+    #[inline]
+    fn get_density(&self) -> u32 {
+        self.buffer[NUM_INTS].load(Ordering::Relaxed)
+    }
+
+    fn store(&self, words: &mut [u32]) {
+        for (word, atomic) in words.iter_mut().zip(self.buffer.iter()) {
+            *word = atomic.load(Ordering::Relaxed);
+        }
+    }
+
+    fn load(words: &[u32]) -> Self {
+        AtomicBBFBlock {
+            buffer: std::array::from_fn(|i| AtomicU32::new(words[i])),
+        }
+    }
+
+    #[inline]
+    fn read_all_words(words: &[u32], hashes: &[usize]) -> bool {
+        super::blanket::read_all_words(words, hashes)
+    }
+
+    #[inline]
+    fn read_all(&self, hashes: &[usize]) -> bool {
+        hashes.iter().all(|hash| self.read(*hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::filters::{blocks::blanket::BlanketBBFBlock, MAX_HASH_COUNT};
+
+    fn random_hashes(rng: &mut StdRng, n: usize) -> Vec<Vec<usize>> {
+        (0..n)
+            .map(|_| {
+                let count = rng.gen_range(1..=MAX_HASH_COUNT);
+                (0..count).map(|_| rng.gen()).collect()
+            })
+            .collect()
+    }
+
+    fn words<B: BBFBlock>(block: &B) -> Vec<u32> {
+        let mut words = vec![0; B::WORDS];
+        block.store(&mut words);
+        words
+    }
+
+    #[test]
+    fn matches_blanket() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let atomic = AtomicBBFBlock::default();
+        let blanket = BlanketBBFBlock::default();
+        for (i, hashes) in random_hashes(&mut rng, 2000).iter().enumerate() {
+            assert_eq!(atomic.read_all(hashes), blanket.read_all(hashes));
+            match i % 3 {
+                0 => assert_eq!(atomic.insert_all(hashes), blanket.insert_all(hashes)),
+                1 => assert_eq!(atomic.insert(hashes[0]), blanket.insert(hashes[0])),
+                _ => {
+                    atomic.insert_all_unchecked(hashes);
+                    blanket.insert_all_unchecked(hashes);
+                }
+            }
+            assert_eq!(atomic.get_density(), blanket.get_density());
+            assert_eq!(atomic.read(hashes[0]), blanket.read(hashes[0]));
+        }
+        assert_eq!(words(&atomic), words(&blanket));
+        let loaded = AtomicBBFBlock::load(&words(&blanket));
+        assert_eq!(words(&loaded), words(&blanket));
+    }
+
+    #[test]
+    fn concurrent_inserts() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let batches: Vec<_> = (0..8).map(|_| random_hashes(&mut rng, 1000)).collect();
+        let atomic = AtomicBBFBlock::default();
+        std::thread::scope(|s| {
+            for batch in &batches {
+                let atomic = &atomic;
+                s.spawn(move || {
+                    for hashes in batch {
+                        atomic.insert_all_unchecked(hashes);
+                    }
+                });
+            }
+        });
+        let blanket = BlanketBBFBlock::default();
+        for hashes in batches.iter().flatten() {
+            blanket.insert_all_unchecked(hashes);
+        }
+        assert_eq!(words(&atomic), words(&blanket));
+        assert!(batches.iter().flatten().all(|h| atomic.read_all(h)));
+    }
+}
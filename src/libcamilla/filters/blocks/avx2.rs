@@ -0,0 +1,287 @@
+use std::arch::x86_64::*;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::{filters::BLOCK_SIZE, utils::hash::mix64};
+
+use super::BBFBlock;
+
+// Number of 256 bit words per block
+pub const AVX2_WORDS: usize = BLOCK_SIZE.div_ceil(256);
+
+// Register blocked implementation of a BBFBlock
+// Every probe of a k-mer lands in the same 256 bit word, selected by the first hash.
+// Eight further bits of each hash pick a lane and a bit within it, so membership
+// is a single vector test and insertion an or of at most four 64 bit lanes.
+// Bits are only ever set, so lanes are read and or-ed atomically with relaxed
+// ordering and no lock is taken, as in AtomicBBFBlock.
+// Only constructible when AVX2 is available at runtime.
+#[repr(C, align(32))]
+pub struct Avx2BBFBlock {
+    words: [[AtomicU64; 4]; AVX2_WORDS],
+    density: AtomicU32,
+}
+
+pub fn avx2_available() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+// Probes of a k-mer are an arithmetic progression of hashes, which a multiplicative
+// hash keeps a progression modulo 256, so each hash is finalized instead
+#[inline]
+fn spread(hash: usize) -> usize {
+    mix64(hash as u64) as usize
+}
+
+// Word index and mask, bit b of lane l being bit 64 * l + b of the 256 bit word
+#[inline]
+fn probe_mask(hashes: &[usize]) -> (usize, [u64; 4]) {
+    let mut mask = [0u64; 4];
+    for hash in hashes.iter().map(|h| spread(*h)) {
+        mask[(hash >> 6) & 3] |= 1 << (hash % 64);
+    }
+    ((spread(hashes[0]) >> 8) % AVX2_WORDS, mask)
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn test_lanes(lanes: &[u64; 4], mask: &[u64; 4]) -> bool {
+    let w = _mm256_loadu_si256(lanes.as_ptr() as *const __m256i);
+    let m = _mm256_loadu_si256(mask.as_ptr() as *const __m256i);
+    _mm256_testc_si256(w, m) != 0
+}
+
+impl Avx2BBFBlock {
+    #[inline]
+    fn load_lanes(&self, index: usize) -> [u64; 4] {
+        let word = &self.words[index];
+        std::array::from_fn(|l| word[l].load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    fn test(&self, hashes: &[usize]) -> bool {
+        let (index, mask) = probe_mask(hashes);
+        // AVX2 support is checked on construction
+        unsafe { test_lanes(&self.load_lanes(index), &mask) }
+    }
+
+    // Set the bits of every hash, returning whether they were all set already
+    #[inline]
+    fn set(&self, hashes: &[usize]) -> bool {
+        let (index, mask) = probe_mask(hashes);
+        let mut res = true;
+        for (lane, mask) in self.words[index].iter().zip(mask) {
+            if mask != 0 {
+                res &= lane.fetch_or(mask, Ordering::Relaxed) & mask == mask;
+            }
+        }
+        res
+    }
+}
+
+impl Clone for Avx2BBFBlock {
+    fn clone(&self) -> Self {
+        Avx2BBFBlock {
+            words: std::array::from_fn(|i| self.load_lanes(i).map(AtomicU64::new)),
+            density: AtomicU32::new(self.density.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Default for Avx2BBFBlock {
+    fn default() -> Self {
+        assert!(avx2_available(), "Avx2BBFBlock requires AVX2");
+        Avx2BBFBlock {
+            words: std::array::from_fn(|_| std::array::from_fn(|_| AtomicU64::new(0))),
+            density: AtomicU32::new(0),
+        }
+    }
+}
+
+impl BBFBlock for Avx2BBFBlock {
+    const LAYOUT: u32 = 2;
+    const WORDS: usize = AVX2_WORDS * 8 + 1;
+
+    #[inline]
+    fn insert(&self, hash: usize) -> bool {
+        let res = self.set(&[hash]);
+        if !res {
+            self.density.fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    #[inline]
+    fn insert_all(&self, hashes: &[usize]) -> bool {
+        let res = self.set(hashes);
+        if !res {
+            self.density.fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    #[inline]
+    fn insert_all_unchecked(&self, hashes: &[usize]) {
+        self.set(hashes);
+        self.density.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn read(&self, hash: usize) -> bool {
+        self.test(&[hash])
+    }
+
+    #[inline]
+    fn get_density(&self) -> u32 {
+        self.density.load(Ordering::Relaxed)
+    }
+
+    // Lanes are persisted as their low then high 32 bits
+    fn store(&self, words: &mut [u32]) {
+        for (chunk, index) in words.chunks_exact_mut(2).zip(0..AVX2_WORDS * 4) {
+            let lane = self.words[index / 4][index % 4].load(Ordering::Relaxed);
+            chunk[0] = lane as u32;
+            chunk[1] = (lane >> 32) as u32;
+        }
+        words[AVX2_WORDS * 8] = self.density.load(Ordering::Relaxed);
+    }
+
+    fn load(words: &[u32]) -> Self {
+        let mut block = Self::default();
+        for (index, chunk) in words[..AVX2_WORDS * 8].chunks_exact(2).enumerate() {
+            *block.words[index / 4][index % 4].get_mut() =
+                chunk[0] as u64 | (chunk[1] as u64) << 32;
+        }
+        *block.density.get_mut() = words[AVX2_WORDS * 8];
+        block
+    }
+
+    // Persisted words are not 32 byte aligned, so test them lane by lane
+    #[inline]
+    fn read_all_words(words: &[u32], hashes: &[usize]) -> bool {
+        let (index, mask) = probe_mask(hashes);
+        words[index * 8..index * 8 + 8]
+            .chunks_exact(2)
+            .zip(mask.iter())
+            .all(|(lane, mask)| (lane[0] as u64 | (lane[1] as u64) << 32) & mask == *mask)
+    }
+
+    #[inline]
+    fn read_all(&self, hashes: &[usize]) -> bool {
+        self.test(hashes)
+    }
+
+    // All probes of a query land in one word, which holds a Poisson number of the keys.
+    // The rate of a 256 bit Bloom filter is averaged over that number.
+    fn block_fpr(load: f64, hash_count: usize) -> f64 {
+        let lambda = load / AVX2_WORDS as f64;
+        let k = hash_count as f64;
+        let clear = (1.0 - 1.0 / 256.0f64).powf(k);
+        let terms = (lambda + 10.0 * lambda.sqrt()) as usize + 10;
+        let mut p = (-lambda).exp();
+        let mut rate = 0.0;
+        for j in 0..=terms {
+            rate += p * (1.0 - clear.powi(j as i32)).powf(k);
+            p *= lambda / (j + 1) as f64;
+        }
+        rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::filters::MAX_HASH_COUNT;
+
+    fn random_hashes(rng: &mut StdRng, n: usize) -> Vec<Vec<usize>> {
+        (0..n)
+            .map(|_| {
+                let count = rng.gen_range(1..=MAX_HASH_COUNT);
+                (0..count).map(|_| rng.gen()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn persisted_words_agree() {
+        if !avx2_available() {
+            eprintln!("skipping persisted_words_agree, AVX2 is unavailable");
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+        let block = Avx2BBFBlock::default();
+        for hashes in random_hashes(&mut rng, 20) {
+            block.insert_all(&hashes);
+        }
+        let mut words = vec![0; Avx2BBFBlock::WORDS];
+        block.store(&mut words);
+        let loaded = Avx2BBFBlock::load(&words);
+        let mut reloaded = vec![0; Avx2BBFBlock::WORDS];
+        loaded.store(&mut reloaded);
+        assert_eq!(words, reloaded);
+        assert_eq!(loaded.get_density(), block.get_density());
+        for hashes in random_hashes(&mut rng, 2000) {
+            let present = block.read_all(&hashes);
+            assert_eq!(loaded.read_all(&hashes), present);
+            assert_eq!(Avx2BBFBlock::read_all_words(&words, &hashes), present);
+        }
+    }
+
+    #[test]
+    fn concurrent_inserts() {
+        if !avx2_available() {
+            eprintln!("skipping concurrent_inserts, AVX2 is unavailable");
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(1);
+        let hashes = random_hashes(&mut rng, 4000);
+        let block = Avx2BBFBlock::default();
+        std::thread::scope(|scope| {
+            for chunk in hashes.chunks(500) {
+                let block = &block;
+                scope.spawn(move || {
+                    for hashes in chunk {
+                        block.insert_all(hashes);
+                        assert!(block.read_all(hashes));
+                    }
+                });
+            }
+        });
+        assert!(hashes.iter().all(|h| block.read_all(h)));
+        assert!(block.get_density() as usize <= hashes.len());
+    }
+
+    #[test]
+    fn block_fpr_matches_random_keys() {
+        if !avx2_available() {
+            eprintln!("skipping block_fpr_matches_random_keys, AVX2 is unavailable");
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(2);
+        for (load, hash_count) in [(150, 8), (250, 6), (400, 4)] {
+            let mut blocks = (0..50).map(|_| Avx2BBFBlock::default()).collect::<Vec<_>>();
+            for block in blocks.iter_mut() {
+                for _ in 0..load {
+                    let hashes: Vec<usize> = (0..hash_count).map(|_| rng.gen()).collect();
+                    block.insert_all_unchecked(&hashes);
+                }
+            }
+            let queries = 200_000;
+            let hits = (0..queries)
+                .filter(|i| {
+                    let hashes: Vec<usize> = (0..hash_count).map(|_| rng.gen()).collect();
+                    blocks[i % blocks.len()].read_all(&hashes)
+                })
+                .count();
+            let observed = hits as f64 / queries as f64;
+            let model = Avx2BBFBlock::block_fpr(load as f64, hash_count);
+            assert!(
+                (observed - model).abs() < 0.1 * model + 5e-5,
+                "{} vs {} at load {}",
+                observed,
+                model,
+                load
+            );
+        }
+    }
+}
@@ -1,9 +1,17 @@
 use std::sync::RwLock;
 
-use crate::filters::{BLOCK_SIZE, HASH_COUNT, NUM_INTS};
+use crate::filters::{BLOCK_SIZE, NUM_INTS};
 
 use super::BBFBlock;
 
+// Membership test over the layout shared by BlanketBBFBlock and AtomicBBFBlock
+#[inline]
+pub(super) fn read_all_words(words: &[u32], hashes: &[usize]) -> bool {
+    hashes
+        .iter()
+        .all(|hash| words[(hash % BLOCK_SIZE) / 32] >> (31 - hash % 32) & 1 != 0)
+}
+
 // Generic implementation of a BBFBlock
 pub struct BlanketBBFBlock {
     buffer: RwLock<[u32; NUM_INTS + 1]>,
@@ -12,7 +20,7 @@ pub struct BlanketBBFBlock {
 impl Clone for BlanketBBFBlock {
     fn clone(&self) -> Self {
         BlanketBBFBlock {
-            buffer: RwLock::new(self.buffer.read().unwrap().clone()),
+            buffer: RwLock::new(*self.buffer.read().unwrap()),
         }
     }
 }
@@ -26,6 +34,9 @@ impl Default for BlanketBBFBlock {
 }
 
 impl BBFBlock for BlanketBBFBlock {
+    const LAYOUT: u32 = 1;
+    const WORDS: usize = NUM_INTS + 1;
+
     #[inline]
     fn insert(&self, hash: usize) -> bool {
         let mut buf = self.buffer.write().unwrap();
@@ -36,7 +47,7 @@ impl BBFBlock for BlanketBBFBlock {
     }
 
     #[inline]
-    fn insert_all(&self, hashes: [usize; HASH_COUNT]) -> bool {
+    fn insert_all(&self, hashes: &[usize]) -> bool {
         let mut buf = self.buffer.write().unwrap();
         let mut res = true;
         for hash in hashes.iter() {
@@ -48,7 +59,7 @@ impl BBFBlock for BlanketBBFBlock {
     }
 
     #[inline]
-    fn insert_all_unchecked(&self, hashes: [usize; HASH_COUNT]) {
+    fn insert_all_unchecked(&self, hashes: &[usize]) {
         let mut buf = self.buffer.write().unwrap();
         for hash in hashes.iter() {
             buf[(hash % BLOCK_SIZE) / 32] |= 1 << (31 - hash % 32);
@@ -68,13 +79,26 @@ impl BBFBlock for BlanketBBFBlock {
         buf[NUM_INTS]
     }
 
+    fn store(&self, words: &mut [u32]) {
+        words.copy_from_slice(&*self.buffer.read().unwrap());
+    }
+
+    fn load(words: &[u32]) -> Self {
+        let mut buffer = [0; NUM_INTS + 1];
+        buffer.copy_from_slice(words);
+        BlanketBBFBlock {
+            buffer: RwLock::new(buffer),
+        }
+    }
+
     #[inline]
-    fn read_all(&self, hashes: [usize; HASH_COUNT]) -> bool {
+    fn read_all_words(words: &[u32], hashes: &[usize]) -> bool {
+        read_all_words(words, hashes)
+    }
+
+    #[inline]
+    fn read_all(&self, hashes: &[usize]) -> bool {
         let buf = self.buffer.read().unwrap();
-        let mut res = true;
-        for hash in hashes.iter() {
-            res &= buf[(hash % BLOCK_SIZE) / 32] >> (31 - hash % 32) & 1 != 0;
-        }
-        res
+        read_all_words(&*buf, hashes)
     }
 }
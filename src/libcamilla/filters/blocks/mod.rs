@@ -1,15 +1,36 @@
-use super::HASH_COUNT;
-
+pub mod atomic;
+#[cfg(target_arch = "x86_64")]
+pub mod avx2;
 pub mod blanket;
 
+use super::BLOCK_SIZE;
+
 // Trait definint Blocked Bloom Filter blocks in an architecture dependent paradigm.
-// Threadsafe by either atomic operations or spinlocks.
+// Threadsafe by atomic operations.
 pub trait BBFBlock: Clone + Send + Sync + Default {
+    // Identifies the persisted bit layout.
+    // Blocks sharing a layout store identical bits and can load each other's filters.
+    const LAYOUT: u32;
+    // Number of u32 words in the persisted layout, with the density as the last word
+    const WORDS: usize;
+
     // Insert utilizing interior mutability
     fn insert(&self, hash: usize) -> bool;
-    fn insert_all(&self, hashes: [usize; HASH_COUNT]) -> bool;
-    fn insert_all_unchecked(&self, hashes: [usize; HASH_COUNT]);
+    fn insert_all(&self, hashes: &[usize]) -> bool;
+    fn insert_all_unchecked(&self, hashes: &[usize]);
     fn read(&self, hash: usize) -> bool;
-    fn read_all(&self, hashes: [usize; HASH_COUNT]) -> bool;
+    fn read_all(&self, hashes: &[usize]) -> bool;
     fn get_density(&self) -> u32;
+    // Copy out the persisted layout, `words` has length WORDS
+    fn store(&self, words: &mut [u32]);
+    fn load(words: &[u32]) -> Self;
+    // read_all over a block in the persisted layout
+    fn read_all_words(words: &[u32], hashes: &[usize]) -> bool;
+
+    // False positive rate of a block holding `load` keys.
+    // Probes spread over the whole block, as in a classic Bloom filter of BLOCK_SIZE bits.
+    fn block_fpr(load: f64, hash_count: usize) -> f64 {
+        let k = hash_count as f64;
+        (1.0 - (-k * load / BLOCK_SIZE as f64).exp()).powf(k)
+    }
 }
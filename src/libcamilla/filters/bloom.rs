@@ -1,9 +1,12 @@
 use crate::{
-    filters::{bucket_hashes::BucketHashes},
-    structures::sequence::{
-        complement::{Complementation, Reversal},
-        packed::{PackedSeq, PackedSeqSlice},
-        storage::Storage,
+    filters::bucket_hashes::BucketHashes,
+    structures::{
+        collection::Collection,
+        sequence::{
+            complement::{Complementation, Reversal},
+            packed::{PackedSeq, PackedSeqSlice},
+            storage::Storage,
+        },
     },
 };
 
@@ -11,70 +14,599 @@ use super::{blocks::*, rolling_hash::RollingHashExt, rolling_hash::RollingHashes
 
 use super::bucket_hashes::BucketHashExt;
 
-use super::BLOCK_SIZE;
+use super::{BLOCK_SIZE, MAX_HASH_COUNT};
+
+use std::{convert::Infallible, mem::size_of};
+
+// Parameters fixing the hashing and layout of a BBFilter
+// Filters are only compatible when these agree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BBFParams {
+    pub k: usize,
+    pub hash_count: usize,
+    pub block_count: usize,
+    pub seed: u64,
+}
 
 // Blocked Bloom Filter
 pub struct BBFilter<B: BBFBlock> {
     blocks: Vec<B>,
+    k: usize,
     block_count: usize,
+    hash_count: usize,
+    seed: u64,
+    expected_keys: usize,
+}
+
+// Probabilists' Gauss-Hermite nodes and weights, for expectations over a normal variable
+const HERMITE: [(f64, f64); 5] = [
+    (0.0, 0.533333),
+    (1.355626, 0.222076),
+    (-1.355626, 0.222076),
+    (2.856970, 0.011257),
+    (-2.856970, 0.011257),
+];
+
+// False positive rate of a filter of B blocks holding `load` keys per block on average.
+// Power of two choices balances distinct minimizers, but the k-mers sharing one share a
+// block pair, so loads are taken as Poisson around the mean. A query reads two blocks.
+pub fn filter_fpr<B: BBFBlock>(load: f64, hash_count: usize) -> f64 {
+    let block = HERMITE
+        .iter()
+        .map(|(x, w)| w * B::block_fpr((load + x * load.sqrt()).max(0.0), hash_count))
+        .sum::<f64>();
+    1.0 - (1.0 - block).powi(2)
+}
+
+// Largest mean load per block keeping the filter under `fpr`
+fn max_load<B: BBFBlock>(fpr: f64, hash_count: usize) -> f64 {
+    let (mut lo, mut hi) = (0.0, BLOCK_SIZE as f64);
+    for _ in 0..40 {
+        let mid = (lo + hi) / 2.0;
+        if filter_fpr::<B>(mid, hash_count) <= fpr {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+// Number of hashes minimizing the size of a filter with the given false positive rate
+pub fn optimal_hash_count<B: BBFBlock>(fpr: f64) -> usize {
+    (1..=MAX_HASH_COUNT)
+        .map(|hash_count| (hash_count, max_load::<B>(fpr, hash_count)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+        .0
+}
+
+// Number of blocks needed to hold `num_keys` keys at the given false positive rate
+pub fn required_block_count<B: BBFBlock>(num_keys: usize, fpr: f64, hash_count: usize) -> usize {
+    ((num_keys as f64 / max_load::<B>(fpr, hash_count)).ceil() as usize).max(1)
 }
 
 impl<B: BBFBlock> BBFilter<B> {
-    pub fn new(num_keys: usize, bits_per_key: usize) -> Self {
+    pub fn new(k: usize, num_keys: usize, bits_per_key: usize, hash_count: usize) -> Self {
+        assert!(
+            (1..=MAX_HASH_COUNT).contains(&hash_count),
+            "Hash count must be between 1 and {}",
+            MAX_HASH_COUNT
+        );
         let size = num_keys * bits_per_key;
-        let block_count = (size + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        Self::with_block_count(k, size.div_ceil(BLOCK_SIZE), hash_count, num_keys)
+    }
+
+    // Size the filter for `expected_kmers` distinct k-mers at a target false positive rate
+    pub fn with_fpr(k: usize, expected_kmers: usize, fpr: f64) -> Self {
+        assert!(
+            fpr > 0.0 && fpr < 1.0,
+            "False positive rate must be in (0, 1)"
+        );
+        let hash_count = optimal_hash_count::<B>(fpr);
+        let block_count = required_block_count::<B>(expected_kmers, fpr, hash_count);
+        Self::with_block_count(k, block_count, hash_count, expected_kmers)
+    }
+
+    // Build an empty filter from parameters, e.g. those suggested by a HyperLogLog
+    pub fn from_params(params: &BBFParams, expected_keys: usize) -> Self {
+        assert!(
+            (1..=MAX_HASH_COUNT).contains(&params.hash_count),
+            "Hash count must be between 1 and {}",
+            MAX_HASH_COUNT
+        );
+        Self::with_block_count(
+            params.k,
+            params.block_count,
+            params.hash_count,
+            expected_keys,
+        )
+        .with_seed(params.seed)
+    }
+
+    // Hash with the given seed instead of unseeded hashing
+    // Filters with equal parameters and seeds, fed the same k-mers, are bit identical.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        debug_assert_eq!(self.len_estimate(), 0, "Reseeding a filter in use");
+        self.seed = seed;
+        self
+    }
+
+    fn with_block_count(
+        k: usize,
+        block_count: usize,
+        hash_count: usize,
+        expected_keys: usize,
+    ) -> Self {
+        let block_count = block_count.max(1);
+        Self::from_blocks(
+            k,
+            hash_count,
+            0,
+            expected_keys,
+            (0..block_count).map(|_| B::default()).collect(),
+        )
+    }
+
+    pub(super) fn from_blocks(
+        k: usize,
+        hash_count: usize,
+        seed: u64,
+        expected_keys: usize,
+        blocks: Vec<B>,
+    ) -> Self {
         Self {
-            blocks: (0..size).map(|_| B::default()).collect(),
-            block_count,
+            k,
+            block_count: blocks.len(),
+            hash_count,
+            seed,
+            expected_keys,
+            blocks,
+        }
+    }
+
+    #[inline]
+    pub(super) fn blocks(&self) -> &[B] {
+        &self.blocks
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    #[inline]
+    pub fn block_count(&self) -> usize {
+        self.block_count
+    }
+
+    #[inline]
+    pub fn hash_count(&self) -> usize {
+        self.hash_count
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn expected_keys(&self) -> usize {
+        self.expected_keys
+    }
+
+    pub fn params(&self) -> BBFParams {
+        BBFParams {
+            k: self.k,
+            hash_count: self.hash_count,
+            block_count: self.block_count,
+            seed: self.seed,
         }
     }
 
-    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>, window_size: usize)
+    // Predicted false positive rate once the expected number of k-mers is inserted
+    pub fn expected_fpr(&self) -> f64 {
+        filter_fpr::<B>(
+            self.expected_keys as f64 / self.block_count as f64,
+            self.hash_count,
+        )
+    }
+
+    // False positive rate at the current load
+    pub fn fpr(&self) -> f64 {
+        filter_fpr::<B>(
+            self.len_estimate() as f64 / self.block_count as f64,
+            self.hash_count,
+        )
+    }
+
+    // Number of distinct k-mers inserted, short of the ones lost to false positives
+    pub fn len_estimate(&self) -> usize {
+        self.blocks.iter().map(|b| b.get_density() as usize).sum()
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.blocks.len() * size_of::<B>()
+    }
+
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>)
     where
         T: Storage,
         R: Reversal,
         C: Complementation,
     {
-        // TODO: better hashing
-        let bhashes = seq.bucket_hash_iter(window_size);
+        if seq.len() < self.k {
+            return;
+        }
 
-        let hashes = seq.rolling_hash_iter(window_size);
+        let bhashes = seq.bucket_hash_iter(self.k, self.seed);
 
-        for ((mut b1, mut b2), hashes) in bhashes.zip(hashes) {
-            if b2 < b1 {
-                std::mem::swap(&mut b1, &mut b2);
-            }
-            let block1 = &self.blocks[(b1 / BLOCK_SIZE) % self.block_count];
-            let block2 = &self.blocks[(b2 / BLOCK_SIZE) % self.block_count];
-            if !block1.read_all(hashes) {
-                if !block2.read_all(hashes) {
-                    if block1.get_density() <= block2.get_density() {
-                        println!("Write to block {}", (b1 / BLOCK_SIZE) % self.block_count);
-                        block1.insert_all_unchecked(hashes);
-                    } else {
-                        println!("Write to block {}", (b2 / BLOCK_SIZE) % self.block_count);
-                        block2.insert_all_unchecked(hashes);
-                    }
-                }
-            }
+        let hashes = seq.rolling_hash_iter(self.k, self.hash_count, self.seed);
+
+        for (bhashes, hashes) in bhashes.zip(hashes) {
+            self.insert_hashes(bhashes, &hashes);
+        }
+    }
+
+    // Insert a single hashed k-mer, returning whether it was already present
+    pub fn insert_hashes(&self, (mut b1, mut b2): BucketHashes, hashes: &RollingHashes) -> bool {
+        if b2 < b1 {
+            std::mem::swap(&mut b1, &mut b2);
+        }
+        let block1 = self.block(b1);
+        let block2 = self.block(b2);
+        if block1.read_all(hashes) || block2.read_all(hashes) {
+            return true;
+        }
+        // Power of two choices: write to the less loaded block
+        if block1.get_density() <= block2.get_density() {
+            block1.insert_all_unchecked(hashes);
+        } else {
+            block2.insert_all_unchecked(hashes);
+        }
+        false
+    }
+
+    // Insert a single k-mer, returning whether it was already present
+    pub fn insert_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        self.insert_hashes(
+            BucketHashes::from_kmer(&kmer, self.seed),
+            &RollingHashes::from_kmer(&kmer, self.hash_count, self.seed),
+        )
+    }
+
+    pub fn contains_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        self.contains_hashes(
+            BucketHashes::from_kmer(&kmer, self.seed),
+            &RollingHashes::from_kmer(&kmer, self.hash_count, self.seed),
+        )
+    }
+
+    #[inline]
+    pub fn contains_hashes(&self, (b1, b2): BucketHashes, hashes: &RollingHashes) -> bool {
+        self.block(b1).read_all(hashes) || self.block(b2).read_all(hashes)
+    }
+
+    #[inline]
+    fn block(&self, bucket: usize) -> &B {
+        &self.blocks[bucket % self.block_count]
+    }
+}
+
+impl<'a, B, T, R, C> Collection<PackedSeqSlice<'a, T, R, C>> for BBFilter<B>
+where
+    B: BBFBlock,
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    type Error = Infallible;
+
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn insert(&self, x: PackedSeqSlice<'a, T, R, C>) -> Result<bool, Infallible> {
+        Ok(self.insert_kmer(x))
+    }
+
+    fn contains(&self, x: PackedSeqSlice<'a, T, R, C>) -> bool {
+        self.contains_kmer(x)
+    }
+
+    fn len_estimate(&self) -> usize {
+        self.len_estimate()
+    }
+
+    fn fpr(&self) -> f64 {
+        self.fpr()
+    }
+}
+
+// BBFilter over the fastest block implementation supported by the running CPU
+pub enum NativeBBFilter {
+    #[cfg(target_arch = "x86_64")]
+    Avx2(BBFilter<avx2::Avx2BBFBlock>),
+    Blanket(BBFilter<blanket::BlanketBBFBlock>),
+}
+
+macro_rules! native_dispatch {
+    ($self:ident, $f:ident => $body:expr) => {
+        match $self {
+            #[cfg(target_arch = "x86_64")]
+            NativeBBFilter::Avx2($f) => $body,
+            NativeBBFilter::Blanket($f) => $body,
+        }
+    };
+}
+
+impl NativeBBFilter {
+    pub fn new(k: usize, num_keys: usize, bits_per_key: usize, hash_count: usize) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        if avx2::avx2_available() {
+            return NativeBBFilter::Avx2(BBFilter::new(k, num_keys, bits_per_key, hash_count));
+        }
+        NativeBBFilter::Blanket(BBFilter::new(k, num_keys, bits_per_key, hash_count))
+    }
+
+    pub fn with_fpr(k: usize, expected_kmers: usize, fpr: f64) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        if avx2::avx2_available() {
+            return NativeBBFilter::Avx2(BBFilter::with_fpr(k, expected_kmers, fpr));
+        }
+        NativeBBFilter::Blanket(BBFilter::with_fpr(k, expected_kmers, fpr))
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            NativeBBFilter::Avx2(f) => NativeBBFilter::Avx2(f.with_seed(seed)),
+            NativeBBFilter::Blanket(f) => NativeBBFilter::Blanket(f.with_seed(seed)),
         }
     }
 
+    pub fn params(&self) -> BBFParams {
+        native_dispatch!(self, f => f.params())
+    }
+
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>)
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        native_dispatch!(self, f => f.insert_kmers(seq))
+    }
+
     pub fn contains_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
     where
         T: Storage,
         R: Reversal,
         C: Complementation,
     {
-        let bhash = BucketHashes::from_kmer(&kmer);
+        native_dispatch!(self, f => f.contains_kmer(kmer))
+    }
+
+    pub fn expected_fpr(&self) -> f64 {
+        native_dispatch!(self, f => f.expected_fpr())
+    }
 
-        let block1 = &self.blocks[(bhash.0 / BLOCK_SIZE) % self.block_count];
-        let block2 = &self.blocks[(bhash.1 / BLOCK_SIZE) % self.block_count];
+    pub fn memory_bytes(&self) -> usize {
+        native_dispatch!(self, f => f.memory_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        filters::NUM_INTS,
+        structures::sequence::complement::{Forward, Identity},
+    };
+
+    const K: usize = 21;
+
+    #[test]
+    fn native_matches_blanket() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let seq = PackedSeq::<u64, Forward, Identity>::random(50_000, &mut rng);
+        let queries = PackedSeq::<u64, Forward, Identity>::random(50_000, &mut rng);
+        let native = NativeBBFilter::with_fpr(K, seq.len(), 0.01).with_seed(7);
+        let blanket =
+            BBFilter::<blanket::BlanketBBFBlock>::with_fpr(K, seq.len(), 0.01).with_seed(7);
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(
+            matches!(native, NativeBBFilter::Avx2(_)),
+            avx2::avx2_available()
+        );
+        // Each block type is sized from its own false positive model
+        match &native {
+            #[cfg(target_arch = "x86_64")]
+            NativeBBFilter::Avx2(_) => {
+                let avx2 =
+                    BBFilter::<avx2::Avx2BBFBlock>::with_fpr(K, seq.len(), 0.01).with_seed(7);
+                assert_eq!(native.params(), avx2.params());
+                assert!(native.params().block_count > blanket.params().block_count);
+            }
+            NativeBBFilter::Blanket(_) => assert_eq!(native.params(), blanket.params()),
+        }
+
+        native.insert_kmers(&seq);
+        blanket.insert_kmers(&seq);
+        for i in 0..=seq.len() - K {
+            assert!(native.contains_kmer(seq.slice(i, K)));
+            assert!(blanket.contains_kmer(seq.slice(i, K)));
+        }
+
+        // Bit layouts differ, so false positives match in rate rather than one by one
+        let count = queries.len() - K + 1;
+        let rate = |contains: &dyn Fn(usize) -> bool| {
+            (0..count).filter(|&i| contains(i)).count() as f64 / count as f64
+        };
+        let native_rate = rate(&|i| native.contains_kmer(queries.slice(i, K)));
+        let blanket_rate = rate(&|i| blanket.contains_kmer(queries.slice(i, K)));
+        for (observed, expected) in [
+            (native_rate, native.expected_fpr()),
+            (blanket_rate, blanket.expected_fpr()),
+        ] {
+            assert!(observed < 2.0 * expected, "{} vs {}", observed, expected);
+        }
+    }
+
+    #[test]
+    fn hash_count_round_trips() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let seq = PackedSeq::<u64, Forward, Identity>::random(2_000, &mut rng);
+        for hash_count in 1..=MAX_HASH_COUNT {
+            let filter = BBFilter::<blanket::BlanketBBFBlock>::new(K, 2_000, 16, hash_count);
+            let params = filter.params();
+            assert_eq!(params.hash_count, hash_count);
+            let rebuilt = BBFilter::<blanket::BlanketBBFBlock>::from_params(&params, 2_000);
+            assert_eq!(rebuilt.params(), params);
+
+            // Bulk and single insertion hash the same positions
+            filter.insert_kmers(&seq);
+            for i in 0..=seq.len() - K {
+                rebuilt.insert_kmer(seq.slice(i, K));
+            }
+            for (a, b) in filter.blocks().iter().zip(rebuilt.blocks()) {
+                let (mut wa, mut wb) = (vec![0; NUM_INTS + 1], vec![0; NUM_INTS + 1]);
+                a.store(&mut wa);
+                b.store(&mut wb);
+                assert_eq!(wa[..NUM_INTS], wb[..NUM_INTS]);
+            }
+        }
+    }
 
-        let hashes = RollingHashes::from_kmer(&kmer);
+    // Rate of random k-mers found in a filter sized for `fpr` and filled to capacity
+    fn observed_fpr<B: BBFBlock>(fpr: f64) -> f64 {
+        let mut rng = StdRng::seed_from_u64(3);
+        let keys = 50_000;
+        let seq = PackedSeq::<u64, Forward, Identity>::random(keys + K - 1, &mut rng);
+        let filter = BBFilter::<B>::with_fpr(K, keys, fpr);
+        filter.insert_kmers(&seq);
+        assert!((filter.fpr() - filter.expected_fpr()).abs() < 0.05 * fpr);
 
-        println!("Attempted Read: {:b} {:b} {}", bhash.0, bhash.1, hashes[0]);
+        let count = (200.0 / fpr) as usize;
+        let queries = PackedSeq::<u64, Forward, Identity>::random(count + K - 1, &mut rng);
+        let hits = queries
+            .bucket_hash_iter(K, 0)
+            .zip(queries.rolling_hash_iter(K, filter.hash_count(), 0))
+            .filter(|(bhashes, hashes)| filter.contains_hashes(*bhashes, hashes))
+            .count();
+        hits as f64 / count as f64
+    }
+
+    fn check_sizing<B: BBFBlock>() {
+        for fpr in [1e-2, 1e-3, 1e-4] {
+            let observed = observed_fpr::<B>(fpr);
+            assert!(
+                observed <= 1.2 * fpr,
+                "{} for a target of {}",
+                observed,
+                fpr
+            );
+        }
+    }
+
+    #[test]
+    fn blanket_meets_target_fpr() {
+        check_sizing::<blanket::BlanketBBFBlock>();
+    }
+
+    #[test]
+    fn atomic_meets_target_fpr() {
+        check_sizing::<atomic::AtomicBBFBlock>();
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn avx2_meets_target_fpr() {
+        if !avx2::avx2_available() {
+            eprintln!("skipping avx2_meets_target_fpr, AVX2 is unavailable");
+            return;
+        }
+        check_sizing::<avx2::Avx2BBFBlock>();
+    }
+
+    #[test]
+    fn hash_count_and_blocks_follow_target() {
+        use blanket::BlanketBBFBlock as B;
+        let mut last_blocks = 0;
+        let mut last_hashes = 0;
+        for fpr in [1e-1, 1e-2, 1e-3, 1e-4, 1e-5] {
+            let hash_count = optimal_hash_count::<B>(fpr);
+            assert!((1..=MAX_HASH_COUNT).contains(&hash_count));
+            assert!(hash_count >= last_hashes);
+            // The chosen count needs no more blocks than any other
+            let blocks = required_block_count::<B>(1_000_000, fpr, hash_count);
+            for other in 1..=MAX_HASH_COUNT {
+                assert!(blocks <= required_block_count::<B>(1_000_000, fpr, other));
+            }
+            assert!(blocks > last_blocks);
+            let load = 1_000_000.0 / blocks as f64;
+            assert!(filter_fpr::<B>(load, hash_count) <= fpr);
+            assert!(filter_fpr::<B>(1_000_000.0 / (blocks - 1) as f64, hash_count) > fpr);
+            last_blocks = blocks;
+            last_hashes = hash_count;
+        }
+        // Blocks scale with the number of keys
+        let small = required_block_count::<B>(10_000, 1e-3, 7);
+        let large = required_block_count::<B>(1_000_000, 1e-3, 7);
+        assert!(large.abs_diff(100 * small) <= 100);
+        assert_eq!(required_block_count::<B>(0, 1e-3, 7), 1);
+    }
+
+    #[test]
+    fn memory_bytes_counts_blocks() {
+        let filter = BBFilter::<blanket::BlanketBBFBlock>::with_fpr(K, 100_000, 1e-3);
+        assert_eq!(
+            filter.memory_bytes(),
+            filter.block_count() * size_of::<blanket::BlanketBBFBlock>()
+        );
+        assert!(filter.memory_bytes() >= filter.block_count() * BLOCK_SIZE / 8);
+        // At the optimum a Bloom filter takes about 1.44 log2(1 / fpr) bits per key
+        let bits_per_key = filter.memory_bytes() as f64 * 8.0 / 100_000.0;
+        assert!(bits_per_key > 1.44 * 1e3f64.log2(), "{}", bits_per_key);
+        assert!(
+            bits_per_key < 2.0 * 1.44 * 1e3f64.log2(),
+            "{}",
+            bits_per_key
+        );
+    }
+
+    // Persisted bytes, header included
+    fn bytes_with_seed(seq: &PackedSeq<u64, Forward, Identity>, seed: u64) -> Vec<u8> {
+        let filter =
+            BBFilter::<blanket::BlanketBBFBlock>::with_fpr(K, 10_000, 1e-2).with_seed(seed);
+        filter.insert_kmers(seq);
+        let mut bytes = Vec::new();
+        filter.write_to(&mut bytes).unwrap();
+        bytes
+    }
 
-        block1.read_all(hashes) || block2.read_all(hashes)
+    #[test]
+    fn seeds_reproduce_filters() {
+        let seq = PackedSeq::random(10_000, &mut StdRng::seed_from_u64(3));
+        let a = bytes_with_seed(&seq, 11);
+        assert_eq!(a, bytes_with_seed(&seq, 11));
+        let b = bytes_with_seed(&seq, 12);
+        assert_eq!(a.len(), b.len());
+        let differing = a.iter().zip(&b).filter(|(x, y)| x != y).count();
+        assert!(differing > a.len() / 4, "{} bytes differ", differing);
     }
 }
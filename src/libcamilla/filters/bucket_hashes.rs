@@ -1,50 +1,89 @@
 //
 
+use std::collections::VecDeque;
+
 use crate::structures::sequence::{
     complement::{Complementation, Reversal},
     packed::{PackedSeq, PackedSeqSlice},
     storage::Storage,
 };
+use crate::utils::hash::mix64;
+
+use super::MAXIMIZER_LENGTH;
 
+// Two candidate buckets, used for power-of-two-choices block selection
 pub type BucketHashes = (usize, usize);
 
+// Salts separating the two bucket choices
+const BUCKET_SALTS: [u64; 2] = [0x9e3779b97f4a7c15, 0xd1b54a32d192ed03];
+
+// Derive both bucket hashes from the hash of a k-mer's minimizer
+#[inline]
+pub fn bucket_hashes(minimizer: u64, seed: u64) -> BucketHashes {
+    let salt = mix64(seed);
+    (
+        mix64(minimizer ^ salt ^ BUCKET_SALTS[0]) as usize,
+        mix64(minimizer ^ salt ^ BUCKET_SALTS[1]) as usize,
+    )
+}
+
+// Minimizers are taken over hashed m-mers, so poly-T runs do not dominate
+// The seed reorders m-mers as well, salt is its mix64 and zero when unseeded.
+#[inline]
+fn mmer_hash(mmer: usize, salt: u64) -> u64 {
+    mix64(mmer as u64 ^ salt)
+}
+
 pub trait BucketHashExt<'a, T: Storage, R: Reversal, C: Complementation> {
-    fn from_kmer(kmer: &PackedSeqSlice<'a, T, R, C>) -> BucketHashes;
+    fn from_kmer(kmer: &PackedSeqSlice<'a, T, R, C>, seed: u64) -> BucketHashes;
 }
 
 impl<'a, T: Storage, R: Reversal, C: Complementation> BucketHashExt<'a, T, R, C> for BucketHashes {
-    fn from_kmer(kmer: &PackedSeqSlice<'a, T, R, C>) -> BucketHashes {
-        let acc = (0..kmer.len).into_iter().fold(0, |acc: usize, i| {
-            acc.wrapping_mul(4).wrapping_add(kmer.get(i) as usize)
-        });
-        let mask = (1 << 2 * kmer.len) - 1;
-        (acc & mask, acc & mask)
+    fn from_kmer(kmer: &PackedSeqSlice<'a, T, R, C>, seed: u64) -> BucketHashes {
+        let salt = mix64(seed);
+        let m = MAXIMIZER_LENGTH.min(kmer.len);
+        let mask = (1 << (2 * m)) - 1;
+        let mut mmer = 0usize;
+        let mut minimizer = u64::MAX;
+        for i in 0..kmer.len {
+            mmer = (mmer.wrapping_mul(4).wrapping_add(kmer.get(i) as usize)) & mask;
+            if i + 1 >= m {
+                minimizer = minimizer.min(mmer_hash(mmer, salt));
+            }
+        }
+        bucket_hashes(minimizer, seed)
     }
 }
 
-// Iterator over fingerprints of all k-mers in a sequence slice
-// Hashes should correspond to the 8-mer maximizer of the k-mer
+// Iterator over bucket hashes of all k-mers in a sequence slice
+// Hashes correspond to the minimizer of the k-mer over its MAXIMIZER_LENGTH-mers
 #[derive(Debug)]
 pub struct BucketHashIter<'a, T: Storage, R: Reversal, C: Complementation> {
     data: PackedSeqSlice<'a, T, R, C>,
     pos: usize,
     window_size: usize,
+    mmer_length: usize,
+    seed: u64,
+    salt: u64,
     buffer: usize,
     mask: usize,
+    // Monotone queue of (start, hash) for the m-mers in the current window
+    window: VecDeque<(usize, u64)>,
 }
 
 impl<'a, T: Storage, R: Reversal, C: Complementation> BucketHashIter<'a, T, R, C> {
-    pub fn new(data: PackedSeqSlice<'a, T, R, C>, window_size: usize) -> Self {
-        let acc = (0..window_size - 1).into_iter().fold(0, |acc: usize, i| {
-            acc.wrapping_mul(4).wrapping_add(data.get(i) as usize)
-        });
-
+    pub fn new(data: PackedSeqSlice<'a, T, R, C>, window_size: usize, seed: u64) -> Self {
+        let mmer_length = MAXIMIZER_LENGTH.min(window_size);
         Self {
             data,
-            pos: window_size - 1,
+            pos: 0,
             window_size,
-            buffer: acc,
-            mask: (1 << 2 * window_size) - 1,
+            mmer_length,
+            seed,
+            salt: mix64(seed),
+            buffer: 0,
+            mask: (1 << (2 * mmer_length)) - 1,
+            window: VecDeque::with_capacity(window_size - mmer_length + 1),
         }
     }
 }
@@ -53,25 +92,40 @@ impl<'a, T: Storage, R: Reversal, C: Complementation> Iterator for BucketHashIte
     type Item = BucketHashes;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // println!("Iter: {}", self.pos);
-        if self.pos >= self.data.len {
-            println!("A {}", self.pos);
-            return None;
-        }
-        self.buffer = self
-            .buffer
-            .wrapping_mul(4)
-            .wrapping_add(self.data.get(self.pos) as usize);
-        self.buffer &= self.mask;
-        self.pos += 1;
-
-        println!(
-            "Bucket Hash {:?} {:b}",
-            self.data.get(self.pos - 1),
-            self.buffer
-        );
+        while self.pos < self.data.len {
+            self.buffer = self
+                .buffer
+                .wrapping_mul(4)
+                .wrapping_add(self.data.get(self.pos) as usize);
+            self.buffer &= self.mask;
+            self.pos += 1;
+
+            if self.pos < self.mmer_length {
+                continue;
+            }
+
+            let start = self.pos - self.mmer_length;
+            let hash = mmer_hash(self.buffer, self.salt);
+            while self.window.back().is_some_and(|&(_, h)| h > hash) {
+                self.window.pop_back();
+            }
+            self.window.push_back((start, hash));
+
+            if self.pos < self.window_size {
+                continue;
+            }
 
-        Some((self.buffer, self.buffer))
+            let kmer_start = self.pos - self.window_size;
+            while self.window.front().is_some_and(|&(s, _)| s < kmer_start) {
+                self.window.pop_front();
+            }
+
+            return self
+                .window
+                .front()
+                .map(|&(_, h)| bucket_hashes(h, self.seed));
+        }
+        None
     }
 }
 
@@ -81,7 +135,102 @@ where
     R: Reversal,
     C: Complementation,
 {
-    pub fn bucket_hash_iter(&self, window_size: usize) -> BucketHashIter<T, R, C> {
-        BucketHashIter::new(self.as_slice(), window_size)
+    pub fn bucket_hash_iter(&self, window_size: usize, seed: u64) -> BucketHashIter<'_, T, R, C> {
+        BucketHashIter::new(self.as_slice(), window_size, seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::complement::{Forward, Identity};
+
+    type Seq = PackedSeq<u64, Forward, Identity>;
+
+    const K: usize = 21;
+
+    // Hash of the minimizer, taken over every m-mer of the k-mer
+    fn naive_minimizer(kmer: &PackedSeqSlice<'_, u64, Forward, Identity>, seed: u64) -> u64 {
+        let m = MAXIMIZER_LENGTH.min(kmer.len);
+        (0..=kmer.len - m)
+            .map(|i| {
+                let mmer = (i..i + m).fold(0usize, |x, j| x << 2 | kmer.get(j) as usize);
+                mmer_hash(mmer, mix64(seed))
+            })
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn iterator_matches_from_kmer() {
+        let seq = Seq::random(3000, &mut StdRng::seed_from_u64(1));
+        for k in [MAXIMIZER_LENGTH - 3, MAXIMIZER_LENGTH, K, 32] {
+            for seed in [0, 17] {
+                let hashes: Vec<_> = seq.bucket_hash_iter(k, seed).collect();
+                assert_eq!(hashes.len(), seq.len() - k + 1);
+                for (i, &hashes) in hashes.iter().enumerate() {
+                    assert_eq!(
+                        hashes,
+                        BucketHashes::from_kmer(&seq.slice(i, k), seed),
+                        "k {} seed {} position {}",
+                        k,
+                        seed,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shared_minimizers_share_buckets() {
+        let seq = Seq::random(20000, &mut StdRng::seed_from_u64(2));
+        let mut by_minimizer = HashMap::new();
+        let mut shared = 0;
+        for (i, hashes) in seq.bucket_hash_iter(K, 0).enumerate() {
+            let (b1, b2) = hashes;
+            assert_ne!(b1, b2);
+            let minimizer = naive_minimizer(&seq.slice(i, K), 0);
+            if let Some(&previous) = by_minimizer.get(&minimizer) {
+                assert_eq!(previous, hashes, "position {}", i);
+                shared += 1;
+            }
+            by_minimizer.insert(minimizer, hashes);
+        }
+        // Consecutive k-mers mostly keep their minimizer
+        assert!(shared > seq.len() / 2, "{} shared", shared);
+    }
+
+    #[test]
+    fn buckets_are_balanced_and_independent() {
+        const BLOCKS: usize = 64;
+        const N: usize = 64 * 1000;
+        let mut first = [0usize; BLOCKS];
+        let mut second = [0usize; BLOCKS];
+        let mut collisions = 0;
+        for minimizer in 0..N as u64 {
+            let (b1, b2) = bucket_hashes(mix64(minimizer), 3);
+            first[b1 % BLOCKS] += 1;
+            second[b2 % BLOCKS] += 1;
+            collisions += (b1 % BLOCKS == b2 % BLOCKS) as usize;
+        }
+        // A thousand keys per block has a standard deviation near 3%
+        let mean = (N / BLOCKS) as f64;
+        for loads in [first, second] {
+            for load in loads {
+                assert!((load as f64 - mean).abs() < 0.15 * mean, "{}", load);
+            }
+        }
+        // Independent choices land in the same block once in BLOCKS
+        let expected = (N / BLOCKS) as f64;
+        assert!(
+            (collisions as f64 - expected).abs() < 0.2 * expected,
+            "{} collisions",
+            collisions
+        );
     }
 }
@@ -0,0 +1,201 @@
+use std::convert::Infallible;
+
+use crate::structures::{
+    collection::Collection,
+    sequence::{
+        complement::{Complementation, Reversal},
+        packed::{PackedSeq, PackedSeqSlice},
+        storage::Storage,
+    },
+};
+
+use super::{blocks::BBFBlock, bloom::BBFilter};
+
+// Cascade of BBFilters approximating abundance thresholds
+// A k-mer moves into level i + 1 only when it was already present in level i,
+// so the last level holds k-mers seen at least as many times as there are levels.
+pub struct CascadeBBFilter<B: BBFBlock> {
+    levels: Vec<BBFilter<B>>,
+}
+
+impl<B: BBFBlock> CascadeBBFilter<B> {
+    // Levels sharing k, hash count and seed, ordered from the first occurrence onwards
+    pub fn from_levels(levels: Vec<BBFilter<B>>) -> Self {
+        assert!(!levels.is_empty(), "A cascade needs at least one level");
+        assert!(
+            levels.iter().all(|l| l.k() == levels[0].k()
+                && l.hash_count() == levels[0].hash_count()
+                && l.seed() == levels[0].seed()),
+            "Cascade levels must share k, hash count and seed"
+        );
+        Self { levels }
+    }
+
+    // Every level sized for all `expected_kmers`, an upper bound for the deeper levels
+    pub fn with_fpr(k: usize, level_count: usize, expected_kmers: usize, fpr: f64) -> Self {
+        Self::from_levels(
+            (0..level_count)
+                .map(|_| BBFilter::with_fpr(k, expected_kmers, fpr))
+                .collect(),
+        )
+    }
+
+    // Hash every level with the given seed
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            levels: self
+                .levels
+                .into_iter()
+                .map(|level| level.with_seed(seed))
+                .collect(),
+        }
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.levels[0].k()
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.levels[0].seed()
+    }
+
+    // Abundance a k-mer needs to reach the last level
+    #[inline]
+    pub fn min_abundance(&self) -> usize {
+        self.levels.len()
+    }
+
+    #[inline]
+    pub fn levels(&self) -> &[BBFilter<B>] {
+        &self.levels
+    }
+
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>)
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        if seq.len() < self.k() {
+            return;
+        }
+
+        let bhashes = seq.bucket_hash_iter(self.k(), self.seed());
+
+        let hashes = seq.rolling_hash_iter(self.k(), self.levels[0].hash_count(), self.seed());
+
+        for (bhashes, hashes) in bhashes.zip(hashes) {
+            for level in self.levels.iter() {
+                if !level.insert_hashes(bhashes, &hashes) {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Number of levels holding the k-mer, capped at min_abundance
+    pub fn count_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> usize
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        self.levels
+            .iter()
+            .take_while(|level| level.contains_kmer(kmer))
+            .count()
+    }
+
+    // Whether the k-mer is solid, present in the last level
+    pub fn contains_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        self.levels.last().unwrap().contains_kmer(kmer)
+    }
+}
+
+// Set of solid k-mers, present in the last level
+impl<'a, B, T, R, C> Collection<PackedSeqSlice<'a, T, R, C>> for CascadeBBFilter<B>
+where
+    B: BBFBlock,
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    type Error = Infallible;
+
+    fn k(&self) -> usize {
+        self.k()
+    }
+
+    // Whether the k-mer was already solid
+    fn insert(&self, x: PackedSeqSlice<'a, T, R, C>) -> Result<bool, Infallible> {
+        for level in self.levels.iter() {
+            if !level.insert_kmer(x) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn contains(&self, x: PackedSeqSlice<'a, T, R, C>) -> bool {
+        self.contains_kmer(x)
+    }
+
+    fn len_estimate(&self) -> usize {
+        self.levels.last().unwrap().len_estimate()
+    }
+
+    fn fpr(&self) -> f64 {
+        self.levels.last().unwrap().fpr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        filters::blocks::blanket::BlanketBBFBlock,
+        structures::sequence::complement::{Forward, Identity},
+    };
+
+    const K: usize = 21;
+
+    #[test]
+    fn solid_kmers_pass_and_singletons_stop() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let solid = PackedSeq::<u64, Forward, Identity>::random(20_000, &mut rng);
+        let twice = PackedSeq::<u64, Forward, Identity>::random(20_000, &mut rng);
+        let once = PackedSeq::<u64, Forward, Identity>::random(20_000, &mut rng);
+        let cascade = CascadeBBFilter::<BlanketBBFBlock>::with_fpr(K, 3, 60_000, 0.01);
+        assert_eq!(cascade.min_abundance(), 3);
+        for _ in 0..3 {
+            cascade.insert_kmers(&solid);
+        }
+        cascade.insert_kmers(&twice);
+        cascade.insert_kmers(&twice);
+        cascade.insert_kmers(&once);
+
+        for i in 0..=solid.len() - K {
+            assert!(cascade.contains_kmer(solid.slice(i, K)));
+            assert_eq!(cascade.count_kmer(solid.slice(i, K)), 3);
+        }
+        let accepted = |seq: &PackedSeq<u64, Forward, Identity>| {
+            (0..=seq.len() - K)
+                .filter(|&i| cascade.contains_kmer(seq.slice(i, K)))
+                .count() as f64
+                / (seq.len() - K + 1) as f64
+        };
+        // A weak k-mer needs a false positive at every level it was not inserted in
+        assert!(accepted(&once) < 0.001, "{}", accepted(&once));
+        assert!(accepted(&twice) < 0.02, "{}", accepted(&twice));
+        assert!((0..=once.len() - K).all(|i| cascade.count_kmer(once.slice(i, K)) >= 1));
+    }
+}
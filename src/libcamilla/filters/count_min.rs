@@ -0,0 +1,344 @@
+use std::{
+    convert::Infallible,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use crate::structures::{
+    collection::Collection,
+    sequence::{
+        complement::{Complementation, Reversal},
+        packed::{PackedSeq, PackedSeqSlice},
+        storage::Storage,
+    },
+};
+
+use super::{
+    rolling_hash::{RollingHashExt, RollingHashes},
+    MAX_HASH_COUNT,
+};
+
+// Count-min sketch over k-mers
+// Each row is indexed by one of the rolling hashes, so depth is at most MAX_HASH_COUNT.
+// With conservative update only the smallest counters are raised, which
+// tightens estimates at the cost of no longer supporting deletions.
+pub struct CountMinSketch {
+    counters: Vec<AtomicU32>,
+    k: usize,
+    width: usize,
+    depth: usize,
+    conservative: bool,
+    seed: u64,
+    total: AtomicU64,
+}
+
+impl CountMinSketch {
+    pub fn new(k: usize, width: usize, depth: usize, conservative: bool) -> Self {
+        assert!(
+            (1..=MAX_HASH_COUNT).contains(&depth),
+            "Depth must be between 1 and {}",
+            MAX_HASH_COUNT
+        );
+        let width = width.max(1);
+        Self {
+            counters: (0..width * depth).map(|_| AtomicU32::new(0)).collect(),
+            k,
+            width,
+            depth,
+            conservative,
+            seed: 0,
+            total: AtomicU64::new(0),
+        }
+    }
+
+    // Estimates exceed true counts by at most epsilon * total with probability 1 - delta
+    // Depth is capped at MAX_HASH_COUNT, bounding delta below by e^-MAX_HASH_COUNT
+    pub fn with_error(k: usize, epsilon: f64, delta: f64, conservative: bool) -> Self {
+        assert!(epsilon > 0.0 && delta > 0.0 && delta < 1.0);
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = ((1.0 / delta).ln().ceil() as usize).clamp(1, MAX_HASH_COUNT);
+        Self::new(k, width, depth, conservative)
+    }
+
+    // Hash with the given seed instead of unseeded hashing
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    // Number of k-mer occurrences inserted
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    // Overestimate bound as a fraction of total, e / width
+    #[inline]
+    pub fn epsilon(&self) -> f64 {
+        std::f64::consts::E / self.width as f64
+    }
+
+    #[inline]
+    fn cell(&self, row: usize, hash: usize) -> &AtomicU32 {
+        &self.counters[row * self.width + hash % self.width]
+    }
+
+    #[inline]
+    fn estimate(&self, hashes: &[usize]) -> u32 {
+        hashes
+            .iter()
+            .enumerate()
+            .map(|(row, hash)| self.cell(row, *hash).load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(0)
+    }
+
+    // Add one occurrence of a hashed k-mer
+    pub fn insert_hashes(&self, hashes: &RollingHashes) {
+        if self.conservative {
+            let target = self.estimate(hashes).saturating_add(1);
+            for (row, hash) in hashes.iter().enumerate() {
+                self.cell(row, *hash).fetch_max(target, Ordering::Relaxed);
+            }
+        } else {
+            for (row, hash) in hashes.iter().enumerate() {
+                self.cell(row, *hash).fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>)
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        if seq.len() < self.k {
+            return;
+        }
+
+        for hashes in seq.rolling_hash_iter(self.k, self.depth, self.seed) {
+            self.insert_hashes(&hashes);
+        }
+    }
+
+    // Estimated number of occurrences, never below the true count
+    pub fn count_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> u32
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        self.estimate(&RollingHashes::from_kmer(&kmer, self.depth, self.seed))
+    }
+
+    // Distinct k-mers, by linear counting over the zero counters of the first row
+    pub fn len_estimate(&self) -> usize {
+        let zeros = self.counters[..self.width]
+            .iter()
+            .filter(|c| c.load(Ordering::Relaxed) == 0)
+            .count();
+        if zeros == 0 {
+            return self.total() as usize;
+        }
+        let width = self.width as f64;
+        ((-width * (zeros as f64 / width).ln()).round() as usize).min(self.total() as usize)
+    }
+
+    // Probability that an absent k-mer has a non zero estimate.
+    // Each of the depth rows hits a used counter with probability about
+    // 1 - e^(-n / width), where width = e / epsilon.
+    pub fn fpr(&self) -> f64 {
+        let load = self.len_estimate() as f64 / self.width as f64;
+        (1.0 - (-load).exp()).powi(self.depth as i32)
+    }
+}
+
+// Set of k-mers with a non zero estimate
+impl<'a, T, R, C> Collection<PackedSeqSlice<'a, T, R, C>> for CountMinSketch
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    type Error = Infallible;
+
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn insert(&self, x: PackedSeqSlice<'a, T, R, C>) -> Result<bool, Infallible> {
+        debug_assert_eq!(x.len, self.k);
+        let hashes = RollingHashes::from_kmer(&x, self.depth, self.seed);
+        let present = self.estimate(&hashes) > 0;
+        self.insert_hashes(&hashes);
+        Ok(present)
+    }
+
+    fn contains(&self, x: PackedSeqSlice<'a, T, R, C>) -> bool {
+        self.count_kmer(x) > 0
+    }
+
+    fn len_estimate(&self) -> usize {
+        self.len_estimate()
+    }
+
+    fn fpr(&self) -> f64 {
+        self.fpr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::complement::{Forward, Identity};
+
+    const K: usize = 21;
+
+    type Seq = PackedSeq<u64, Forward, Identity>;
+
+    // Sequences repeated a skewed number of times, with the exact k-mer counts
+    fn skewed(rng: &mut StdRng) -> (Vec<(Seq, u32)>, HashMap<String, u32>) {
+        let seqs = (0..200).map(|_| Seq::random(100, rng)).collect::<Vec<_>>();
+        let mut repeated = Vec::new();
+        let mut exact = HashMap::new();
+        for seq in seqs {
+            let times = if rng.gen_bool(0.1) {
+                rng.gen_range(10..50)
+            } else {
+                1
+            };
+            for i in 0..=seq.len() - K {
+                *exact.entry(seq.slice(i, K).to_string()).or_insert(0) += times;
+            }
+            repeated.push((seq, times));
+        }
+        (repeated, exact)
+    }
+
+    fn estimates(
+        sketch: &CountMinSketch,
+        seqs: &[(Seq, u32)],
+        exact: &HashMap<String, u32>,
+    ) -> Vec<(u32, u32)> {
+        let mut out = Vec::new();
+        for (seq, _) in seqs {
+            for i in 0..=seq.len() - K {
+                let kmer = seq.slice(i, K);
+                out.push((sketch.count_kmer(kmer), exact[&kmer.to_string()]));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn never_undercounts() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (seqs, exact) = skewed(&mut rng);
+        for conservative in [false, true] {
+            let sketch = CountMinSketch::new(K, 1000, 3, conservative);
+            for (seq, times) in &seqs {
+                for _ in 0..*times {
+                    sketch.insert_kmers(seq);
+                }
+            }
+            assert_eq!(
+                sketch.total(),
+                exact.values().map(|&n| n as u64).sum::<u64>()
+            );
+            assert!(estimates(&sketch, &seqs, &exact)
+                .iter()
+                .all(|(estimate, truth)| estimate >= truth));
+        }
+    }
+
+    #[test]
+    fn error_within_bound() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (seqs, exact) = skewed(&mut rng);
+        let mut last_error = f64::INFINITY;
+        for (width, depth) in [(500, 2), (2000, 3), (8000, 5)] {
+            let plain = CountMinSketch::new(K, width, depth, false);
+            let conservative = CountMinSketch::new(K, width, depth, true);
+            for (seq, times) in &seqs {
+                for _ in 0..*times {
+                    plain.insert_kmers(seq);
+                    conservative.insert_kmers(seq);
+                }
+            }
+            let bound = plain.epsilon() * plain.total() as f64;
+            let plain = estimates(&plain, &seqs, &exact);
+            let conservative = estimates(&conservative, &seqs, &exact);
+
+            // Estimates exceed the bound with probability at most e^-depth
+            let over = plain
+                .iter()
+                .filter(|(estimate, truth)| (estimate - truth) as f64 > bound)
+                .count();
+            assert!(
+                (over as f64) <= (-(depth as f64)).exp() * plain.len() as f64,
+                "{} of {} over {}",
+                over,
+                plain.len(),
+                bound
+            );
+            // Conservative update never raises an estimate
+            assert!(plain.iter().zip(&conservative).all(|(p, c)| c.0 <= p.0));
+
+            let error = plain.iter().map(|(e, t)| (e - t) as f64).sum::<f64>() / plain.len() as f64;
+            assert!(error < last_error);
+            last_error = error;
+        }
+    }
+
+    fn counters(sketch: &CountMinSketch) -> Vec<u32> {
+        sketch
+            .counters
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    #[test]
+    fn seeds_reproduce_counters() {
+        let (seqs, _) = skewed(&mut StdRng::seed_from_u64(3));
+        let with_seed = |seed| {
+            let sketch = CountMinSketch::new(K, 1024, 4, true).with_seed(seed);
+            for (seq, times) in &seqs {
+                for _ in 0..*times {
+                    sketch.insert_kmers(seq);
+                }
+            }
+            counters(&sketch)
+        };
+        let a = with_seed(11);
+        assert_eq!(a, with_seed(11));
+        let differing = a.iter().zip(&with_seed(12)).filter(|(x, y)| x != y).count();
+        assert!(differing > a.len() / 4, "{} counters differ", differing);
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    convert::Infallible,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::structures::{
+    collection::Collection,
+    sequence::{
+        complement::{Complementation, Reversal},
+        packed::{PackedSeq, PackedSeqSlice},
+        storage::Storage,
+    },
+};
+
+use super::{
+    blocks::blanket::BlanketBBFBlock,
+    bloom::{filter_fpr, optimal_hash_count, required_block_count},
+    bucket_hashes::{BucketHashExt, BucketHashes},
+    rolling_hash::{RollingHashExt, RollingHashes},
+    MAX_HASH_COUNT, NUM_INTS,
+};
+
+// Block of saturating BITS wide counters, with the same footprint as a BBFBlock
+pub struct CountingBlock<const BITS: usize> {
+    buffer: [AtomicU32; NUM_INTS + 1],
+}
+
+impl<const BITS: usize> CountingBlock<BITS> {
+    const PER_WORD: usize = 32 / BITS;
+    const SLOTS: usize = NUM_INTS * Self::PER_WORD;
+    const MAX: u32 = (1 << BITS) - 1;
+
+    #[inline]
+    fn locate(hash: usize) -> (usize, u32) {
+        let slot = hash % Self::SLOTS;
+        (
+            slot / Self::PER_WORD,
+            ((slot % Self::PER_WORD) * BITS) as u32,
+        )
+    }
+
+    #[inline]
+    fn counter(&self, hash: usize) -> u32 {
+        let (word, shift) = Self::locate(hash);
+        (self.buffer[word].load(Ordering::Relaxed) >> shift) & Self::MAX
+    }
+
+    #[inline]
+    fn increment(&self, hash: usize) {
+        let (word, shift) = Self::locate(hash);
+        let _ = self.buffer[word].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |w| {
+            if (w >> shift) & Self::MAX == Self::MAX {
+                None
+            } else {
+                Some(w + (1 << shift))
+            }
+        });
+    }
+
+    // Smallest counter among the hashes, an upper bound on the k-mer count
+    #[inline]
+    pub fn count_all(&self, hashes: &[usize]) -> u32 {
+        hashes.iter().map(|h| self.counter(*h)).min().unwrap_or(0)
+    }
+
+    #[inline]
+    pub fn increment_all(&self, hashes: &[usize]) {
+        for hash in hashes {
+            self.increment(*hash);
+        }
+    }
+
+    #[inline]
+    fn increment_density(&self) {
+        self.buffer[NUM_INTS].fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get_density(&self) -> u32 {
+        self.buffer[NUM_INTS].load(Ordering::Relaxed)
+    }
+}
+
+impl<const BITS: usize> Default for CountingBlock<BITS> {
+    fn default() -> Self {
+        CountingBlock {
+            buffer: std::array::from_fn(|_| AtomicU32::new(0)),
+        }
+    }
+}
+
+// Counting Blocked Bloom Filter
+// Blocks are chosen by the same BucketHashes as BBFilter. A k-mer is counted in
+// whichever candidate block holds its larger count, and read as the larger one.
+pub struct CountingBBFilter<const BITS: usize = 4> {
+    blocks: Vec<CountingBlock<BITS>>,
+    k: usize,
+    hash_count: usize,
+    seed: u64,
+    min_abundance: u32,
+}
+
+impl<const BITS: usize> CountingBBFilter<BITS> {
+    pub fn new(k: usize, block_count: usize, hash_count: usize, min_abundance: u32) -> Self {
+        const { assert!(BITS == 4 || BITS == 8, "Counters must be 4 or 8 bits wide") };
+        assert!(
+            (1..=MAX_HASH_COUNT).contains(&hash_count),
+            "Hash count must be between 1 and {}",
+            MAX_HASH_COUNT
+        );
+        assert!(
+            min_abundance <= CountingBlock::<BITS>::MAX,
+            "Minimum abundance exceeds the counter range"
+        );
+        Self {
+            blocks: (0..block_count.max(1))
+                .map(|_| CountingBlock::default())
+                .collect(),
+            k,
+            hash_count,
+            seed: 0,
+            min_abundance,
+        }
+    }
+
+    // Size for `expected_kmers` distinct k-mers at a target false positive rate
+    // Each counter takes BITS bits, so BITS times the blocks of a BBFilter are needed
+    pub fn with_fpr(k: usize, expected_kmers: usize, fpr: f64, min_abundance: u32) -> Self {
+        assert!(
+            fpr > 0.0 && fpr < 1.0,
+            "False positive rate must be in (0, 1)"
+        );
+        let hash_count = optimal_hash_count::<BlanketBBFBlock>(fpr);
+        let block_count =
+            required_block_count::<BlanketBBFBlock>(expected_kmers, fpr, hash_count) * BITS;
+        Self::new(k, block_count, hash_count, min_abundance)
+    }
+
+    // Hash with the given seed instead of unseeded hashing
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    #[inline]
+    pub fn min_abundance(&self) -> u32 {
+        self.min_abundance
+    }
+
+    // Largest count a counter can hold
+    #[inline]
+    pub fn saturation(&self) -> u32 {
+        CountingBlock::<BITS>::MAX
+    }
+
+    #[inline]
+    fn block(&self, bucket: usize) -> &CountingBlock<BITS> {
+        &self.blocks[bucket % self.blocks.len()]
+    }
+
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>)
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        if seq.len() < self.k {
+            return;
+        }
+
+        let bhashes = seq.bucket_hash_iter(self.k, self.seed);
+
+        let hashes = seq.rolling_hash_iter(self.k, self.hash_count, self.seed);
+
+        for (bhashes, hashes) in bhashes.zip(hashes) {
+            self.insert_hashes(bhashes, &hashes);
+        }
+    }
+
+    // Add one occurrence of a hashed k-mer, returning whether it was already present
+    pub fn insert_hashes(&self, (mut b1, mut b2): BucketHashes, hashes: &RollingHashes) -> bool {
+        if b2 < b1 {
+            std::mem::swap(&mut b1, &mut b2);
+        }
+        let block1 = self.block(b1);
+        let block2 = self.block(b2);
+        let (count1, count2) = (block1.count_all(hashes), block2.count_all(hashes));
+        if count1 > 0 || count2 > 0 {
+            // Counting on in the block with the larger count keeps the larger of the
+            // two at or above the true count, even when the other is a false positive
+            if count1 >= count2 {
+                block1.increment_all(hashes);
+            } else {
+                block2.increment_all(hashes);
+            }
+            true
+        } else {
+            // Power of two choices: first occurrence goes to the less loaded block
+            let block = if block1.get_density() <= block2.get_density() {
+                block1
+            } else {
+                block2
+            };
+            block.increment_density();
+            block.increment_all(hashes);
+            false
+        }
+    }
+
+    // Estimated number of occurrences, never below the true count unless saturated
+    pub fn count_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> u32
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        let bhash = BucketHashes::from_kmer(&kmer, self.seed);
+
+        let hashes = RollingHashes::from_kmer(&kmer, self.hash_count, self.seed);
+
+        self.block(bhash.0)
+            .count_all(&hashes)
+            .max(self.block(bhash.1).count_all(&hashes))
+    }
+
+    // Whether the k-mer is solid, seen at least min_abundance times
+    pub fn contains_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        self.count_kmer(kmer) >= self.min_abundance
+    }
+
+    // Number of distinct k-mers inserted, short of the ones lost to false positives
+    pub fn len_estimate(&self) -> usize {
+        self.blocks.iter().map(|b| b.get_density() as usize).sum()
+    }
+
+    // False positive rate at the current load for a min_abundance of one, an upper bound otherwise.
+    // A block holds BITS times fewer counters than a BlanketBBFBlock holds bits.
+    pub fn fpr(&self) -> f64 {
+        filter_fpr::<BlanketBBFBlock>(
+            (self.len_estimate() * BITS) as f64 / self.blocks.len() as f64,
+            self.hash_count,
+        )
+    }
+}
+
+// Set of solid k-mers, seen at least min_abundance times
+impl<'a, const BITS: usize, T, R, C> Collection<PackedSeqSlice<'a, T, R, C>>
+    for CountingBBFilter<BITS>
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    type Error = Infallible;
+
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    // Whether the k-mer was already solid
+    fn insert(&self, x: PackedSeqSlice<'a, T, R, C>) -> Result<bool, Infallible> {
+        debug_assert_eq!(x.len, self.k);
+        let solid = self.contains_kmer(x);
+        self.insert_hashes(
+            BucketHashes::from_kmer(&x, self.seed),
+            &RollingHashes::from_kmer(&x, self.hash_count, self.seed),
+        );
+        Ok(solid)
+    }
+
+    fn contains(&self, x: PackedSeqSlice<'a, T, R, C>) -> bool {
+        self.contains_kmer(x)
+    }
+
+    fn len_estimate(&self) -> usize {
+        self.len_estimate()
+    }
+
+    fn fpr(&self) -> f64 {
+        self.fpr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::complement::{Forward, Identity};
+
+    const K: usize = 21;
+
+    type Seq = PackedSeq<u64, Forward, Identity>;
+
+    // Distinct k-mers inserted between one and `max` times, in shuffled order
+    fn insert_repeats(
+        filter: &CountingBBFilter<4>,
+        seq: &Seq,
+        max: u32,
+        rng: &mut StdRng,
+    ) -> Vec<u32> {
+        let counts = (0..=seq.len() - K)
+            .map(|_| rng.gen_range(1..=max))
+            .collect::<Vec<_>>();
+        let mut order = counts
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &n)| std::iter::repeat_n(i, n as usize))
+            .collect::<Vec<_>>();
+        for i in (1..order.len()).rev() {
+            order.swap(i, rng.gen_range(0..=i));
+        }
+        for i in order {
+            Collection::insert(filter, seq.slice(i, K)).unwrap();
+        }
+        counts
+    }
+
+    #[test]
+    fn never_undercounts() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let seq = Seq::random(20_000, &mut rng);
+        // Overloaded, so k-mers often find false positive counts in their other block
+        let filter = CountingBBFilter::<4>::new(K, 100, 3, 1);
+        let counts = insert_repeats(&filter, &seq, 6, &mut rng);
+        let mut exact = HashMap::new();
+        for (i, n) in counts.iter().enumerate() {
+            *exact.entry(seq.slice(i, K).to_string()).or_insert(0) += n;
+        }
+        for i in 0..=seq.len() - K {
+            let truth = exact[&seq.slice(i, K).to_string()].min(filter.saturation());
+            assert!(filter.count_kmer(seq.slice(i, K)) >= truth);
+        }
+    }
+
+    #[test]
+    fn solid_kmers_reach_min_abundance() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let seq = Seq::random(20_000, &mut rng);
+        let filter = CountingBBFilter::<4>::with_fpr(K, seq.len(), 0.001, 3);
+        let counts = insert_repeats(&filter, &seq, 5, &mut rng);
+        let mut weak = 0;
+        let mut weak_accepted = 0;
+        for (i, &n) in counts.iter().enumerate() {
+            let solid = filter.contains_kmer(seq.slice(i, K));
+            if n >= 3 {
+                assert!(solid);
+            } else {
+                weak += 1;
+                weak_accepted += solid as usize;
+            }
+        }
+        assert!(
+            (weak_accepted as f64) < 0.01 * weak as f64,
+            "{} of {}",
+            weak_accepted,
+            weak
+        );
+        assert!((filter.len_estimate() as f64) > 0.99 * counts.len() as f64);
+    }
+
+    #[test]
+    fn counts_saturate() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let seq = Seq::random(200, &mut rng);
+        let filter = CountingBBFilter::<4>::with_fpr(K, seq.len(), 0.001, 15);
+        let wide = CountingBBFilter::<8>::with_fpr(K, seq.len(), 0.001, 15);
+        for _ in 0..40 {
+            filter.insert_kmers(&seq);
+            wide.insert_kmers(&seq);
+        }
+        assert_eq!(filter.saturation(), 15);
+        assert_eq!(wide.saturation(), 255);
+        for i in 0..=seq.len() - K {
+            assert_eq!(filter.count_kmer(seq.slice(i, K)), 15);
+            assert!(filter.contains_kmer(seq.slice(i, K)));
+            assert!(wide.count_kmer(seq.slice(i, K)) >= 40);
+        }
+    }
+}
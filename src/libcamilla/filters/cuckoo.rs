@@ -0,0 +1,420 @@
+/*
+Cuckoo filter over k-mers, supporting deletion.
+
+Every k-mer is reduced to a 16 bit fingerprint stored in one of two buckets
+of BUCKET_SIZE slots. The second bucket is the first xored with a hash of the
+fingerprint, so either can be recovered from the other when evicting.
+Every insertion stores a copy of the fingerprint, even when it is already
+present, and a deletion removes one copy. Two k-mers sharing a bucket and
+fingerprint thus keep a copy each, and a k-mer inserted n times needs n
+deletions. At most 2 * BUCKET_SIZE copies of a fingerprint fit in its two
+buckets, further insertions fail with FilterError::Full. Deleting a k-mer
+that was never inserted may remove a colliding one.
+ */
+use std::sync::RwLock;
+
+use crate::{
+    structures::{
+        collection::Collection,
+        sequence::{
+            complement::{Complementation, Reversal},
+            packed::{PackedSeq, PackedSeqSlice},
+            storage::Storage,
+        },
+    },
+    utils::hash::mix64,
+};
+
+use super::{
+    error::FilterError,
+    rolling_hash::{RollingHashExt, RollingHashes},
+};
+
+pub const BUCKET_SIZE: usize = 4;
+// Relocations attempted before an insertion is declared failed
+const MAX_KICKS: usize = 500;
+// Highest load factor reached reliably with 4 slot buckets
+const MAX_LOAD: f64 = 0.95;
+
+// Zero marks an empty slot
+type Fingerprint = u16;
+
+struct CuckooTable {
+    buckets: Vec<[Fingerprint; BUCKET_SIZE]>,
+    len: usize,
+    // Fingerprint left homeless by a failed insertion, still reported as present
+    victim: Option<(usize, Fingerprint)>,
+}
+
+impl CuckooTable {
+    #[inline]
+    fn has(&self, bucket: usize, fp: Fingerprint) -> bool {
+        self.buckets[bucket].contains(&fp)
+    }
+
+    // Copies of a fingerprint held by its buckets, including the victim slot
+    fn copies(&self, b1: usize, b2: usize, fp: Fingerprint) -> usize {
+        let count = |b: usize| self.buckets[b].iter().filter(|&&f| f == fp).count();
+        let victim = self
+            .victim
+            .is_some_and(|(b, f)| f == fp && (b == b1 || b == b2));
+        count(b1) + if b1 == b2 { 0 } else { count(b2) } + victim as usize
+    }
+
+    #[inline]
+    fn put(&mut self, bucket: usize, fp: Fingerprint) -> bool {
+        match self.buckets[bucket].iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fp;
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[inline]
+    fn take(&mut self, bucket: usize, fp: Fingerprint) -> bool {
+        match self.buckets[bucket].iter_mut().find(|slot| **slot == fp) {
+            Some(slot) => {
+                *slot = 0;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct CuckooFilter {
+    table: RwLock<CuckooTable>,
+    k: usize,
+    seed: u64,
+    mask: usize,
+}
+
+impl CuckooFilter {
+    // Filter able to hold `capacity` k-mers
+    pub fn new(k: usize, capacity: usize) -> Self {
+        let buckets = ((capacity as f64 / (BUCKET_SIZE as f64 * MAX_LOAD)).ceil() as usize)
+            .max(1)
+            .next_power_of_two();
+        Self {
+            table: RwLock::new(CuckooTable {
+                buckets: vec![[0; BUCKET_SIZE]; buckets],
+                len: 0,
+                victim: None,
+            }),
+            k,
+            seed: 0,
+            mask: buckets - 1,
+        }
+    }
+
+    // Hash with the given seed instead of unseeded hashing
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    // Number of stored k-mers, counting every copy
+    pub fn len(&self) -> usize {
+        self.table.read().unwrap().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        (self.mask + 1) * BUCKET_SIZE
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len() as f64 / self.capacity() as f64
+    }
+
+    // False positive rate at the current load, a query compares against two buckets
+    pub fn expected_fpr(&self) -> f64 {
+        // Fingerprint 0 is stored as 1, which is then twice as likely as any other value
+        let values = (1u32 << Fingerprint::BITS) as f64;
+        let collision = (values + 2.0) / (values * values);
+        let compared = 2.0 * BUCKET_SIZE as f64 * self.load_factor();
+        1.0 - (1.0 - collision).powf(compared)
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        (self.mask + 1) * std::mem::size_of::<[Fingerprint; BUCKET_SIZE]>()
+    }
+
+    // Primary bucket and fingerprint of a hashed k-mer
+    #[inline]
+    fn locate(&self, hash: usize) -> (usize, Fingerprint) {
+        let fp = (hash >> 48) as Fingerprint;
+        (hash & self.mask, fp.max(1))
+    }
+
+    #[inline]
+    fn alternate(&self, bucket: usize, fp: Fingerprint) -> usize {
+        (bucket ^ mix64(fp as u64) as usize) & self.mask
+    }
+
+    // Insert a copy of a single hashed k-mer, returning whether it was already present
+    pub fn insert_hash(&self, hash: usize) -> Result<bool, FilterError> {
+        let (b1, fp) = self.locate(hash);
+        let b2 = self.alternate(b1, fp);
+        let mut table = self.table.write().unwrap();
+        let copies = table.copies(b1, b2, fp);
+        let room = if b1 == b2 {
+            BUCKET_SIZE
+        } else {
+            2 * BUCKET_SIZE
+        };
+        if table.victim.is_some() || copies >= room {
+            return Err(FilterError::Full);
+        }
+        let present = copies > 0;
+        table.len += 1;
+        if table.put(b1, fp) || table.put(b2, fp) {
+            return Ok(present);
+        }
+
+        // Relocate fingerprints along a random walk, seeded by the hash
+        let mut state = mix64(hash as u64) | 1;
+        let (mut bucket, mut fp) = (if state & (1 << 32) == 0 { b1 } else { b2 }, fp);
+        for _ in 0..MAX_KICKS {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let slot = state as usize % BUCKET_SIZE;
+            std::mem::swap(&mut fp, &mut table.buckets[bucket][slot]);
+            bucket = self.alternate(bucket, fp);
+            if table.put(bucket, fp) {
+                return Ok(present);
+            }
+        }
+        // The table is consistent, the last evicted fingerprint waits in the victim slot
+        table.victim = Some((bucket, fp));
+        Ok(present)
+    }
+
+    pub fn contains_hash(&self, hash: usize) -> bool {
+        let (b1, fp) = self.locate(hash);
+        let b2 = self.alternate(b1, fp);
+        let table = self.table.read().unwrap();
+        table.has(b1, fp)
+            || table.has(b2, fp)
+            || table
+                .victim
+                .is_some_and(|(b, f)| f == fp && (b == b1 || b == b2))
+    }
+
+    // Remove one copy of a single hashed k-mer, returning whether it was present
+    pub fn delete_hash(&self, hash: usize) -> bool {
+        let (b1, fp) = self.locate(hash);
+        let b2 = self.alternate(b1, fp);
+        let mut table = self.table.write().unwrap();
+        let victim = table.victim;
+        if victim.is_some_and(|(b, f)| f == fp && (b == b1 || b == b2)) {
+            table.victim = None;
+        } else if !table.take(b1, fp) && !table.take(b2, fp) {
+            return false;
+        }
+        table.len -= 1;
+        // A freed slot lets the victim back in
+        if let Some((bucket, fp)) = table.victim {
+            let alternate = self.alternate(bucket, fp);
+            if table.put(bucket, fp) || table.put(alternate, fp) {
+                table.victim = None;
+            }
+        }
+        true
+    }
+
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>) -> Result<(), FilterError>
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        if seq.len() < self.k {
+            return Ok(());
+        }
+        for hashes in seq.rolling_hash_iter(self.k, 1, self.seed) {
+            self.insert_hash(hashes[0])?;
+        }
+        Ok(())
+    }
+
+    // Remove every k-mer of a sequence, returning how many were present
+    pub fn delete_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>) -> usize
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        if seq.len() < self.k {
+            return 0;
+        }
+        seq.rolling_hash_iter(self.k, 1, self.seed)
+            .filter(|hashes| self.delete_hash(hashes[0]))
+            .count()
+    }
+
+    pub fn insert_kmer<'a, T, R, C>(
+        &self,
+        kmer: PackedSeqSlice<'a, T, R, C>,
+    ) -> Result<bool, FilterError>
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        self.insert_hash(RollingHashes::from_kmer(&kmer, 1, self.seed)[0])
+    }
+
+    pub fn contains_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        self.contains_hash(RollingHashes::from_kmer(&kmer, 1, self.seed)[0])
+    }
+
+    pub fn delete_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        self.delete_hash(RollingHashes::from_kmer(&kmer, 1, self.seed)[0])
+    }
+}
+
+impl<'a, T, R, C> Collection<PackedSeqSlice<'a, T, R, C>> for CuckooFilter
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    type Error = FilterError;
+
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn insert(&self, x: PackedSeqSlice<'a, T, R, C>) -> Result<bool, FilterError> {
+        self.insert_kmer(x)
+    }
+
+    fn contains(&self, x: PackedSeqSlice<'a, T, R, C>) -> bool {
+        self.contains_kmer(x)
+    }
+
+    fn len_estimate(&self) -> usize {
+        self.len()
+    }
+
+    fn fpr(&self) -> f64 {
+        self.expected_fpr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn shared_fingerprint() {
+        let filter = CuckooFilter::new(21, 1000);
+        // Same bucket and fingerprint, different k-mers
+        let (h1, h2) = (0xabcd_0000_0000_0012, 0xabcd_0000_0100_0012);
+        assert_eq!(filter.locate(h1), filter.locate(h2));
+        assert!(!filter.insert_hash(h1).unwrap());
+        assert!(filter.insert_hash(h2).unwrap());
+        assert!(filter.delete_hash(h1));
+        assert!(filter.contains_hash(h2));
+        assert!(filter.delete_hash(h2));
+        assert!(!filter.contains_hash(h2));
+        assert!(filter.is_empty());
+    }
+
+    #[test]
+    fn copy_bound() {
+        let filter = CuckooFilter::new(21, 1000);
+        let hash = 0x1234_5678_9abc_def0;
+        for _ in 0..2 * BUCKET_SIZE {
+            filter.insert_hash(hash).unwrap();
+        }
+        assert!(matches!(filter.insert_hash(hash), Err(FilterError::Full)));
+        for _ in 0..2 * BUCKET_SIZE {
+            assert!(filter.delete_hash(hash));
+        }
+        assert!(!filter.contains_hash(hash));
+    }
+
+    #[test]
+    fn no_false_negatives() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let filter = CuckooFilter::new(21, 20_000);
+        // Few distinct hashes, so that copies and shared fingerprints are common
+        let pool: Vec<usize> = (0..4000)
+            .map(|_| (rng.gen::<usize>() & !(0xff << 48)) | (rng.gen_range(0..4usize) << 48))
+            .collect();
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for step in 0..40_000 {
+            let hash = pool[rng.gen_range(0..pool.len())];
+            let count = counts.entry(hash).or_default();
+            if *count > 0 && rng.gen_bool(0.5) {
+                assert!(filter.delete_hash(hash));
+                *count -= 1;
+            } else if filter.len() < 15_000 && filter.insert_hash(hash).is_ok() {
+                *count += 1;
+            }
+            if step % 1000 == 0 {
+                assert!(counts
+                    .iter()
+                    .all(|(h, c)| *c == 0 || filter.contains_hash(*h)));
+            }
+        }
+        assert!(counts
+            .iter()
+            .all(|(h, c)| *c == 0 || filter.contains_hash(*h)));
+        assert_eq!(filter.len(), counts.values().sum::<usize>());
+    }
+
+    #[test]
+    fn fpr_matches_model() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let filter = CuckooFilter::new(21, 100_000);
+        for _ in 0..90_000 {
+            filter.insert_hash(rng.gen()).unwrap();
+        }
+        let queries = 1_000_000;
+        let hits = (0..queries)
+            .filter(|_| filter.contains_hash(rng.gen()))
+            .count();
+        let measured = hits as f64 / queries as f64;
+        let expected = filter.expected_fpr();
+        assert!(
+            (measured - expected).abs() < 0.3 * expected,
+            "{measured} vs {expected}"
+        );
+    }
+}
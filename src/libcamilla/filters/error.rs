@@ -0,0 +1,77 @@
+use std::{error::Error, fmt::Display, io};
+
+use super::{bloom::BBFParams, quotient::QFParams};
+
+#[derive(Debug)]
+pub enum FilterError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    InvalidHeader,
+    // Persisted blocks have a different bit layout than the requested block type
+    LayoutMismatch {
+        expected: u32,
+        found: u32,
+    },
+    ParamMismatch {
+        expected: BBFParams,
+        found: BBFParams,
+    },
+    QFParamMismatch {
+        expected: QFParams,
+        found: QFParams,
+    },
+    Truncated,
+    // No room is left for another key
+    Full,
+    // Keys cannot be inserted through a derived view of other filters
+    ReadOnly,
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterError::Io(e) => write!(f, "I/O error: {}", e),
+            FilterError::BadMagic => write!(f, "Not a Camilla filter file"),
+            FilterError::UnsupportedVersion(v) => write!(f, "Unsupported format version {}", v),
+            FilterError::InvalidHeader => write!(f, "Invalid filter header"),
+            FilterError::LayoutMismatch { expected, found } => write!(
+                f,
+                "Block layout mismatch: expected {}, found {}",
+                expected, found
+            ),
+            FilterError::ParamMismatch { expected, found } => write!(
+                f,
+                "Filter parameter mismatch: expected {:?}, found {:?}",
+                expected, found
+            ),
+            FilterError::QFParamMismatch { expected, found } => write!(
+                f,
+                "Quotient filter parameter mismatch: expected {:?}, found {:?}",
+                expected, found
+            ),
+            FilterError::Truncated => write!(f, "Filter file is truncated"),
+            FilterError::Full => write!(f, "Filter is full"),
+            FilterError::ReadOnly => write!(f, "Filter is read-only"),
+        }
+    }
+}
+
+impl Error for FilterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FilterError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FilterError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            FilterError::Truncated
+        } else {
+            FilterError::Io(e)
+        }
+    }
+}
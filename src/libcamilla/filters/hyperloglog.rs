@@ -0,0 +1,219 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::structures::sequence::{
+    complement::{Complementation, Reversal},
+    packed::PackedSeq,
+    storage::Storage,
+};
+
+use super::{
+    blocks::BBFBlock,
+    bloom::{optimal_hash_count, required_block_count, BBFParams},
+};
+
+// HyperLogLog estimator of the number of distinct k-mers
+// Registers are updated with atomic max, so sequences can be streamed from many threads.
+pub struct HyperLogLog {
+    registers: Vec<AtomicU8>,
+    k: usize,
+    precision: u32,
+    seed: u64,
+}
+
+impl HyperLogLog {
+    // 2^precision registers, for a relative standard error of 1.04 / 2^(precision / 2)
+    pub fn new(k: usize, precision: u32) -> Self {
+        assert!(
+            (4..=18).contains(&precision),
+            "Precision must be between 4 and 18"
+        );
+        Self {
+            registers: (0..1usize << precision).map(|_| AtomicU8::new(0)).collect(),
+            k,
+            precision,
+            seed: 0,
+        }
+    }
+
+    // Hash with the given seed instead of unseeded hashing
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    #[inline]
+    pub fn insert_hash(&self, hash: u64) {
+        let index = (hash >> (64 - self.precision)) as usize;
+        // Leading zeros of the remaining bits, plus one
+        let rank = ((hash << self.precision) | (1 << (self.precision - 1))).leading_zeros() + 1;
+        self.registers[index].fetch_max(rank as u8, Ordering::Relaxed);
+    }
+
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>)
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        if seq.len() < self.k {
+            return;
+        }
+
+        for hashes in seq.rolling_hash_iter(self.k, 1, self.seed) {
+            self.insert_hash(hashes[0] as u64);
+        }
+    }
+
+    // Estimated number of distinct k-mers
+    pub fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let (sum, zeros) = self
+            .registers
+            .iter()
+            .map(|r| r.load(Ordering::Relaxed))
+            .fold((0.0, 0usize), |(sum, zeros), r| {
+                (sum + (-(r as f64)).exp2(), zeros + (r == 0) as usize)
+            });
+        let raw = alpha * m * m / sum;
+        // Linear counting is more accurate while many registers are empty
+        if raw <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            raw.round() as usize
+        }
+    }
+
+    // Relative standard error of the estimate
+    #[inline]
+    pub fn std_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+
+    // Approximate 95% confidence interval on the number of distinct k-mers
+    pub fn bounds(&self) -> (usize, usize) {
+        let estimate = self.estimate() as f64;
+        let margin = 1.96 * self.std_error() * estimate;
+        (
+            (estimate - margin).max(0.0).floor() as usize,
+            (estimate + margin).ceil() as usize,
+        )
+    }
+
+    // Parameters of a BBFilter over B blocks reaching `fpr` for the upper bound on distinct k-mers
+    // The filter inherits the seed of the estimator.
+    pub fn suggest_params<B: BBFBlock>(&self, fpr: f64) -> BBFParams {
+        let hash_count = optimal_hash_count::<B>(fpr);
+        BBFParams {
+            k: self.k,
+            hash_count,
+            block_count: required_block_count::<B>(self.bounds().1, fpr, hash_count),
+            seed: self.seed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        filters::{blocks::blanket::BlanketBBFBlock, bloom::BBFilter},
+        structures::sequence::complement::{Forward, Identity},
+    };
+
+    const K: usize = 31;
+
+    type Seq = PackedSeq<u64, Forward, Identity>;
+
+    fn relative_error(hll: &HyperLogLog, truth: usize) -> f64 {
+        (hll.estimate() as f64 - truth as f64).abs() / truth as f64
+    }
+
+    #[test]
+    fn small_range_is_linear_counting() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let hll = HyperLogLog::new(K, 14);
+        assert_eq!(hll.estimate(), 0);
+        let seq = Seq::random(2_000 + K - 1, &mut rng);
+        hll.insert_kmers(&seq);
+        // Repeats do not count
+        hll.insert_kmers(&seq);
+        // Linear counting over mostly empty registers has a standard error near 0.6% here
+        assert!(relative_error(&hll, 2_000) < 0.02, "{}", hll.estimate());
+        let tiny = HyperLogLog::new(K, 14);
+        tiny.insert_kmers(&Seq::random(10 + K - 1, &mut rng));
+        assert_eq!(tiny.estimate(), 10);
+    }
+
+    #[test]
+    fn large_range_within_std_error() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for (precision, truth) in [(10, 100_000), (12, 400_000)] {
+            let hll = HyperLogLog::new(K, precision);
+            hll.insert_kmers(&Seq::random(truth + K - 1, &mut rng));
+            assert!(
+                relative_error(&hll, truth) < 3.0 * hll.std_error(),
+                "{} for {}",
+                hll.estimate(),
+                truth
+            );
+            let (low, high) = hll.bounds();
+            assert!(low <= hll.estimate() && hll.estimate() <= high);
+        }
+    }
+
+    #[test]
+    fn suggested_filter_meets_fpr() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let seq = Seq::random(100_000 + K - 1, &mut rng);
+        let hll = HyperLogLog::new(K, 12).with_seed(5);
+        hll.insert_kmers(&seq);
+        let params = hll.suggest_params::<BlanketBBFBlock>(0.01);
+        assert_eq!((params.k, params.seed), (K, 5));
+
+        let filter = BBFilter::<BlanketBBFBlock>::from_params(&params, hll.bounds().1);
+        assert_eq!(filter.params(), params);
+        filter.insert_kmers(&seq);
+        // Sized for the upper bound, so the true count stays within the target
+        assert!(filter.fpr() <= 0.01, "{}", filter.fpr());
+        assert!(filter.fpr() > 0.002, "{}", filter.fpr());
+    }
+
+    fn registers(hll: &HyperLogLog) -> Vec<u8> {
+        hll.registers
+            .iter()
+            .map(|r| r.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    #[test]
+    fn seeds_reproduce_registers() {
+        let seq = Seq::random(50_000, &mut StdRng::seed_from_u64(3));
+        let with_seed = |seed| {
+            let hll = HyperLogLog::new(K, 12).with_seed(seed);
+            hll.insert_kmers(&seq);
+            registers(&hll)
+        };
+        let a = with_seed(11);
+        assert_eq!(a, with_seed(11));
+        let differing = a.iter().zip(&with_seed(12)).filter(|(x, y)| x != y).count();
+        assert!(differing > a.len() / 2, "{} registers differ", differing);
+    }
+}
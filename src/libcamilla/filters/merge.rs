@@ -0,0 +1,243 @@
+use crate::structures::{
+    collection::Collection,
+    sequence::{
+        complement::{Complementation, Reversal},
+        packed::PackedSeqSlice,
+        storage::Storage,
+    },
+};
+
+use super::{blocks::BBFBlock, bloom::BBFilter, error::FilterError};
+
+// Estimated number of keys in a block from its set bits.
+// Clamped to the range the merged densities allow.
+fn estimate_density(bits: &[u32], hash_count: usize, min: u32, max: u32) -> u32 {
+    let m = (bits.len() * 32) as f64;
+    let set = bits.iter().map(|w| w.count_ones()).sum::<u32>() as f64;
+    let estimate = if set >= m {
+        max as f64
+    } else {
+        -(m / hash_count as f64) * (1.0 - set / m).ln()
+    };
+    (estimate.round() as u32).clamp(min, max)
+}
+
+impl<B: BBFBlock> BBFilter<B> {
+    // Merge the bits of two filters built with identical parameters.
+    // The density of every block is kept last in the persisted layout.
+    fn merge(
+        &self,
+        other: &Self,
+        op: fn(u32, u32) -> u32,
+        bounds: fn(u32, u32) -> (u32, u32),
+        expected_keys: usize,
+    ) -> Result<Self, FilterError> {
+        if self.params() != other.params() {
+            return Err(FilterError::ParamMismatch {
+                expected: self.params(),
+                found: other.params(),
+            });
+        }
+
+        let mut words = vec![0u32; B::WORDS];
+        let mut other_words = vec![0u32; B::WORDS];
+        let blocks = self
+            .blocks()
+            .iter()
+            .zip(other.blocks())
+            .map(|(a, b)| {
+                a.store(&mut words);
+                b.store(&mut other_words);
+                let (density, bits) = words.split_last_mut().unwrap();
+                let other_density = other_words[B::WORDS - 1];
+                for (w, o) in bits.iter_mut().zip(other_words.iter()) {
+                    *w = op(*w, *o);
+                }
+                let (min, max) = bounds(*density, other_density);
+                *density = estimate_density(bits, self.hash_count(), min, max);
+                B::load(&words)
+            })
+            .collect();
+
+        Ok(Self::from_blocks(
+            self.k(),
+            self.hash_count(),
+            self.seed(),
+            expected_keys,
+            blocks,
+        ))
+    }
+
+    // Filter containing every k-mer of either filter
+    pub fn union(&self, other: &Self) -> Result<Self, FilterError> {
+        self.merge(
+            other,
+            |a, b| a | b,
+            |a, b| (a.max(b), a.saturating_add(b)),
+            self.expected_keys() + other.expected_keys(),
+        )
+    }
+
+    // Set of the k-mers held by both filters.
+    // Each filter chooses between the two candidate blocks of a k-mer by its own
+    // densities, so the k-mer can sit in different blocks of the two filters and
+    // intersecting blocks would lose it. Both filters are queried instead.
+    pub fn intersection<'f>(
+        &'f self,
+        other: &'f Self,
+    ) -> Result<BBFIntersection<'f, B>, FilterError> {
+        if self.params() != other.params() {
+            return Err(FilterError::ParamMismatch {
+                expected: self.params(),
+                found: other.params(),
+            });
+        }
+        Ok(BBFIntersection {
+            filters: [self, other],
+        })
+    }
+}
+
+pub struct BBFIntersection<'f, B: BBFBlock> {
+    filters: [&'f BBFilter<B>; 2],
+}
+
+impl<'f, B: BBFBlock> BBFIntersection<'f, B> {
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.filters[0].k()
+    }
+
+    #[inline]
+    pub fn filters(&self) -> [&'f BBFilter<B>; 2] {
+        self.filters
+    }
+
+    pub fn contains_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        self.filters.iter().all(|f| f.contains_kmer(kmer))
+    }
+
+    // Upper bound on the number of shared k-mers
+    pub fn len_estimate(&self) -> usize {
+        self.filters[0]
+            .len_estimate()
+            .min(self.filters[1].len_estimate())
+    }
+
+    // False positive rate for k-mers held by one of the filters, the worst case
+    pub fn fpr(&self) -> f64 {
+        self.filters[0].fpr().max(self.filters[1].fpr())
+    }
+}
+
+impl<'a, 'f, B, T, R, C> Collection<PackedSeqSlice<'a, T, R, C>> for BBFIntersection<'f, B>
+where
+    B: BBFBlock,
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    type Error = FilterError;
+
+    fn k(&self) -> usize {
+        self.k()
+    }
+
+    // The operands are borrowed, inserting would change the filters they were taken from
+    fn insert(&self, _: PackedSeqSlice<'a, T, R, C>) -> Result<bool, FilterError> {
+        Err(FilterError::ReadOnly)
+    }
+
+    fn contains(&self, x: PackedSeqSlice<'a, T, R, C>) -> bool {
+        self.contains_kmer(x)
+    }
+
+    fn len_estimate(&self) -> usize {
+        self.len_estimate()
+    }
+
+    fn fpr(&self) -> f64 {
+        self.fpr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        filters::blocks::blanket::BlanketBBFBlock,
+        structures::sequence::{
+            complement::{Forward, Identity},
+            packed::PackedSeq,
+        },
+    };
+
+    const K: usize = 21;
+
+    fn kmers(
+        seq: &PackedSeq<u64, Forward, Identity>,
+    ) -> impl Iterator<Item = PackedSeqSlice<'_, u64, Forward, Identity>> {
+        (0..=seq.len() - K).map(move |i| seq.slice(i, K))
+    }
+
+    #[test]
+    fn no_false_negatives() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let shared = PackedSeq::<u64, Forward, Identity>::random(20_000, &mut rng);
+        let only_a = PackedSeq::<u64, Forward, Identity>::random(20_000, &mut rng);
+        let only_b = PackedSeq::<u64, Forward, Identity>::random(20_000, &mut rng);
+        let a = BBFilter::<BlanketBBFBlock>::with_fpr(K, 40_000, 0.01);
+        let b = BBFilter::<BlanketBBFBlock>::with_fpr(K, 40_000, 0.01);
+        // Different insertion orders give different block choices
+        a.insert_kmers(&only_a);
+        a.insert_kmers(&shared);
+        b.insert_kmers(&shared);
+        b.insert_kmers(&only_b);
+
+        let both = a.intersection(&b).unwrap();
+        assert!(kmers(&shared).all(|x| both.contains_kmer(x)));
+        let exclusive = kmers(&only_a).filter(|x| both.contains_kmer(*x)).count();
+        assert!((exclusive as f64) < 0.05 * only_a.len() as f64);
+
+        let either = a.union(&b).unwrap();
+        assert!(kmers(&shared)
+            .chain(kmers(&only_a))
+            .chain(kmers(&only_b))
+            .all(|x| either.contains_kmer(x)));
+    }
+
+    #[test]
+    fn intersection_is_read_only() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let seq = PackedSeq::<u64, Forward, Identity>::random(1_000, &mut rng);
+        let a = BBFilter::<BlanketBBFBlock>::with_fpr(K, 1000, 0.01);
+        let b = BBFilter::<BlanketBBFBlock>::with_fpr(K, 1000, 0.01);
+        let both = a.intersection(&b).unwrap();
+        assert!(
+            kmers(&seq).all(|x| matches!(Collection::insert(&both, x), Err(FilterError::ReadOnly)))
+        );
+        assert_eq!(a.len_estimate() + b.len_estimate(), 0);
+        assert!(kmers(&seq).all(|x| !Collection::contains(&both, x)));
+    }
+
+    #[test]
+    fn param_mismatch() {
+        let a = BBFilter::<BlanketBBFBlock>::with_fpr(K, 1000, 0.01);
+        let b = BBFilter::<BlanketBBFBlock>::with_fpr(K, 1000, 0.01).with_seed(1);
+        assert!(matches!(
+            a.intersection(&b),
+            Err(FilterError::ParamMismatch { .. })
+        ));
+        assert!(matches!(
+            a.union(&b),
+            Err(FilterError::ParamMismatch { .. })
+        ));
+    }
+}
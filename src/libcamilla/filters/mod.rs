@@ -1,11 +1,26 @@
 pub mod blocks;
 pub mod bloom;
 pub mod bucket_hashes;
+pub mod cascade;
+pub mod count_min;
+pub mod counting;
+pub mod cuckoo;
+pub mod error;
+pub mod hyperloglog;
+pub mod merge;
+pub mod persist;
+pub mod quotient;
 pub mod rolling_hash;
+pub mod stats;
+// Persisted words are little endian and read in place
+#[cfg(target_endian = "little")]
+pub mod view;
 
 pub const NUM_INTS: usize = 127;
 pub const BLOCK_SIZE: usize = 32 * NUM_INTS;
-pub const HASH_COUNT: usize = 1;
+// Upper bound on the runtime hash count
+pub const MAX_HASH_COUNT: usize = 8;
 pub const MAXIMIZER_LENGTH: usize = 8;
 
-const_assert!(HASH_COUNT <= 8);
+// Minimizer m-mers are packed in a usize, along with the mask one past them
+const_assert!(2 * MAXIMIZER_LENGTH < usize::BITS as usize);
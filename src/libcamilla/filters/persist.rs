@@ -0,0 +1,491 @@
+/*
+Versioned on-disk format for BBFilters.
+
+All integers are little endian.
+    [0, 8)    magic "CAMBBF\0\0"
+    [8, 12)   format version
+    [12, 16)  block layout
+    [16, 24)  k
+    [24, 32)  hash count
+    [32, 40)  block count
+    [40, 48)  hash seed
+    [48, 56)  expected number of keys
+    [56, 64)  u32 words per block
+followed by the words of every block, in block order.
+ */
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use memmap2::Mmap;
+
+use super::{
+    blocks::BBFBlock,
+    bloom::{BBFParams, BBFilter},
+    error::FilterError,
+    MAX_HASH_COUNT,
+};
+
+pub const MAGIC: [u8; 8] = *b"CAMBBF\0\0";
+pub const FORMAT_VERSION: u32 = 1;
+pub const HEADER_LEN: usize = 64;
+// Blocks reserved up front when reading a stream of unknown length
+const MAX_PREALLOC_BLOCKS: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BBFHeader {
+    pub layout: u32,
+    pub params: BBFParams,
+    pub expected_keys: usize,
+    pub words: usize,
+}
+
+#[inline]
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+#[inline]
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+impl BBFHeader {
+    pub fn of<B: BBFBlock>(filter: &BBFilter<B>) -> Self {
+        Self {
+            layout: B::LAYOUT,
+            params: filter.params(),
+            expected_keys: filter.expected_keys(),
+            words: B::WORDS,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..8].copy_from_slice(&MAGIC);
+        bytes[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.layout.to_le_bytes());
+        bytes[16..24].copy_from_slice(&(self.params.k as u64).to_le_bytes());
+        bytes[24..32].copy_from_slice(&(self.params.hash_count as u64).to_le_bytes());
+        bytes[32..40].copy_from_slice(&(self.params.block_count as u64).to_le_bytes());
+        bytes[40..48].copy_from_slice(&self.params.seed.to_le_bytes());
+        bytes[48..56].copy_from_slice(&(self.expected_keys as u64).to_le_bytes());
+        bytes[56..64].copy_from_slice(&(self.words as u64).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FilterError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(FilterError::Truncated);
+        }
+        if bytes[0..8] != MAGIC {
+            return Err(FilterError::BadMagic);
+        }
+        let version = read_u32(bytes, 8);
+        if version != FORMAT_VERSION {
+            return Err(FilterError::UnsupportedVersion(version));
+        }
+        let header = Self {
+            layout: read_u32(bytes, 12),
+            params: BBFParams {
+                k: read_u64(bytes, 16) as usize,
+                hash_count: read_u64(bytes, 24) as usize,
+                block_count: read_u64(bytes, 32) as usize,
+                seed: read_u64(bytes, 40),
+            },
+            expected_keys: read_u64(bytes, 48) as usize,
+            words: read_u64(bytes, 56) as usize,
+        };
+        if header.params.k == 0
+            || header.params.block_count == 0
+            || !(1..=MAX_HASH_COUNT).contains(&header.params.hash_count)
+        {
+            return Err(FilterError::InvalidHeader);
+        }
+        header.body_len()?;
+        Ok(header)
+    }
+
+    // Reject blocks persisted in a different layout
+    pub fn check_layout<B: BBFBlock>(&self) -> Result<(), FilterError> {
+        if self.layout != B::LAYOUT || self.words != B::WORDS {
+            return Err(FilterError::LayoutMismatch {
+                expected: B::LAYOUT,
+                found: self.layout,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_params(&self, expected: &BBFParams) -> Result<(), FilterError> {
+        if self.params != *expected {
+            return Err(FilterError::ParamMismatch {
+                expected: *expected,
+                found: self.params,
+            });
+        }
+        Ok(())
+    }
+
+    // Length in bytes of the block section, rejecting headers whose file would not fit in memory
+    #[inline]
+    pub fn body_len(&self) -> Result<usize, FilterError> {
+        self.params
+            .block_count
+            .checked_mul(self.words)
+            .and_then(|words| words.checked_mul(4))
+            .filter(|len| len.checked_add(HEADER_LEN).is_some())
+            .ok_or(FilterError::InvalidHeader)
+    }
+
+    // Reject files too short for the blocks the header announces
+    pub fn check_len(&self, file_len: u64) -> Result<(), FilterError> {
+        if file_len < (HEADER_LEN + self.body_len()?) as u64 {
+            return Err(FilterError::Truncated);
+        }
+        Ok(())
+    }
+}
+
+fn decode_block<B: BBFBlock>(bytes: &[u8], words: &mut [u32]) -> B {
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    B::load(words)
+}
+
+impl<B: BBFBlock> BBFilter<B> {
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), FilterError> {
+        writer.write_all(&BBFHeader::of(self).to_bytes())?;
+        let mut words = vec![0u32; B::WORDS];
+        let mut bytes = Vec::with_capacity(B::WORDS * 4);
+        for block in self.blocks() {
+            block.store(&mut words);
+            bytes.clear();
+            bytes.extend(words.iter().flat_map(|w| w.to_le_bytes()));
+            writer.write_all(&bytes)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FilterError> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    // Read a filter, rejecting it unless its parameters equal `expected` when given
+    pub fn read_from<Rd: Read>(
+        reader: Rd,
+        expected: Option<&BBFParams>,
+    ) -> Result<Self, FilterError> {
+        Self::read_checked(reader, expected, None)
+    }
+
+    // Read a filter from a stream of `len` bytes when known
+    fn read_checked<Rd: Read>(
+        mut reader: Rd,
+        expected: Option<&BBFParams>,
+        len: Option<u64>,
+    ) -> Result<Self, FilterError> {
+        let mut bytes = [0; HEADER_LEN];
+        reader.read_exact(&mut bytes)?;
+        let header = BBFHeader::from_bytes(&bytes)?;
+        header.check_layout::<B>()?;
+        if let Some(expected) = expected {
+            header.check_params(expected)?;
+        }
+        if let Some(len) = len {
+            header.check_len(len)?;
+        }
+
+        let mut words = vec![0u32; B::WORDS];
+        let mut bytes = vec![0u8; B::WORDS * 4];
+        // A truncated stream fails on read, before growing past what it holds
+        let reserved = match len {
+            Some(_) => header.params.block_count,
+            None => header.params.block_count.min(MAX_PREALLOC_BLOCKS),
+        };
+        let mut blocks = Vec::with_capacity(reserved);
+        for _ in 0..header.params.block_count {
+            reader.read_exact(&mut bytes)?;
+            blocks.push(decode_block(&bytes, &mut words));
+        }
+
+        Ok(Self::from_blocks(
+            header.params.k,
+            header.params.hash_count,
+            header.params.seed,
+            header.expected_keys,
+            blocks,
+        ))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, FilterError> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Self::read_checked(BufReader::new(file), None, Some(len))
+    }
+
+    pub fn load_matching<P: AsRef<Path>>(path: P, params: &BBFParams) -> Result<Self, FilterError> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Self::read_checked(BufReader::new(file), Some(params), Some(len))
+    }
+
+    // Load through a memory map, avoiding buffered reads of large filters
+    pub fn load_mmap<P: AsRef<Path>>(path: P) -> Result<Self, FilterError> {
+        let file = File::open(path)?;
+        // The map is dropped before returning, so only concurrent truncation could invalidate it
+        let map = unsafe { Mmap::map(&file)? };
+        let header = BBFHeader::from_bytes(&map)?;
+        header.check_layout::<B>()?;
+        header.check_len(map.len() as u64)?;
+
+        let mut words = vec![0u32; B::WORDS];
+        let blocks = map[HEADER_LEN..HEADER_LEN + header.body_len()?]
+            .chunks_exact(B::WORDS * 4)
+            .map(|bytes| decode_block(bytes, &mut words))
+            .collect();
+
+        Ok(Self::from_blocks(
+            header.params.k,
+            header.params.hash_count,
+            header.params.seed,
+            header.expected_keys,
+            blocks,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        filters::blocks::{atomic::AtomicBBFBlock, blanket::BlanketBBFBlock},
+        structures::sequence::{
+            complement::{Forward, Identity},
+            packed::PackedSeq,
+        },
+    };
+
+    fn header_bytes(block_count: usize, words: usize) -> Vec<u8> {
+        let filter = BBFilter::<BlanketBBFBlock>::new(5, 100, 10, 4);
+        let mut header = BBFHeader::of(&filter);
+        header.params.block_count = block_count;
+        header.words = words;
+        header.to_bytes().to_vec()
+    }
+
+    #[test]
+    fn oversized_body_is_invalid() {
+        let bytes = header_bytes(usize::MAX / 2, BlanketBBFBlock::WORDS);
+        assert!(matches!(
+            BBFHeader::from_bytes(&bytes),
+            Err(FilterError::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn truncated_body_fails_before_allocating() {
+        let mut bytes = header_bytes(1 << 40, BlanketBBFBlock::WORDS);
+        bytes.extend_from_slice(&[0; 64]);
+        let header = BBFHeader::from_bytes(&bytes).unwrap();
+        assert!(matches!(
+            header.check_len(bytes.len() as u64),
+            Err(FilterError::Truncated)
+        ));
+        assert!(BBFilter::<BlanketBBFBlock>::read_from(&bytes[..], None).is_err());
+    }
+
+    const K: usize = 21;
+
+    fn filled<B: BBFBlock>(rng: &mut StdRng) -> (BBFilter<B>, PackedSeq<u64, Forward, Identity>) {
+        let seq = PackedSeq::<u64, Forward, Identity>::random(20_000, rng);
+        let filter = BBFilter::<B>::with_fpr(K, seq.len(), 0.05).with_seed(3);
+        filter.insert_kmers(&seq);
+        (filter, seq)
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("camilla-{}-{}.bbf", name, std::process::id()))
+    }
+
+    // Every way of reading a filter back answers as the original
+    fn round_trip<B: BBFBlock>(name: &str) {
+        let mut rng = StdRng::seed_from_u64(4);
+        let (filter, seq) = filled::<B>(&mut rng);
+        let queries = PackedSeq::<u64, Forward, Identity>::random(20_000, &mut rng);
+
+        let mut bytes = Vec::new();
+        filter.write_to(&mut bytes).unwrap();
+        let read = BBFilter::<B>::read_from(&bytes[..], Some(&filter.params())).unwrap();
+
+        let path = temp_path(name);
+        filter.save(&path).unwrap();
+        let loaded = BBFilter::<B>::load(&path).unwrap();
+        let matching = BBFilter::<B>::load_matching(&path, &filter.params()).unwrap();
+        let mapped = BBFilter::<B>::load_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for copy in [&read, &loaded, &matching, &mapped] {
+            assert_eq!(copy.params(), filter.params());
+            assert_eq!(copy.expected_keys(), filter.expected_keys());
+            assert_eq!(copy.len_estimate(), filter.len_estimate());
+        }
+        for kmers in [&seq, &queries] {
+            for i in 0..=kmers.len() - K {
+                let kmer = kmers.slice(i, K);
+                let present = filter.contains_kmer(kmer);
+                for copy in [&read, &loaded, &matching, &mapped] {
+                    assert_eq!(copy.contains_kmer(kmer), present);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blanket_round_trip() {
+        round_trip::<BlanketBBFBlock>("blanket");
+    }
+
+    #[test]
+    fn atomic_round_trip() {
+        round_trip::<AtomicBBFBlock>("atomic");
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_round_trip() {
+        if !crate::filters::blocks::avx2::avx2_available() {
+            eprintln!("skipping avx2_round_trip, AVX2 is unavailable");
+            return;
+        }
+        round_trip::<crate::filters::blocks::avx2::Avx2BBFBlock>("avx2");
+    }
+
+    #[test]
+    fn shared_layouts_load_each_other() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let (filter, seq) = filled::<BlanketBBFBlock>(&mut rng);
+        let mut bytes = Vec::new();
+        filter.write_to(&mut bytes).unwrap();
+        let atomic = BBFilter::<AtomicBBFBlock>::read_from(&bytes[..], None).unwrap();
+        let mut again = Vec::new();
+        atomic.write_to(&mut again).unwrap();
+        assert_eq!(bytes, again);
+        assert!((0..=seq.len() - K).all(|i| atomic.contains_kmer(seq.slice(i, K))));
+    }
+
+    #[test]
+    fn corrupt_headers_are_rejected() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let (filter, _) = filled::<BlanketBBFBlock>(&mut rng);
+        let mut bytes = Vec::new();
+        filter.write_to(&mut bytes).unwrap();
+        let read = |bytes: &[u8]| BBFilter::<BlanketBBFBlock>::read_from(bytes, None);
+
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(matches!(read(&magic), Err(FilterError::BadMagic)));
+
+        let mut version = bytes.clone();
+        version[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            read(&version),
+            Err(FilterError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+
+        let mut hashes = bytes.clone();
+        hashes[24..32].copy_from_slice(&(MAX_HASH_COUNT as u64 + 1).to_le_bytes());
+        assert!(matches!(read(&hashes), Err(FilterError::InvalidHeader)));
+
+        assert!(matches!(
+            read(&bytes[..HEADER_LEN - 1]),
+            Err(FilterError::Truncated)
+        ));
+        assert!(matches!(
+            read(&bytes[..bytes.len() - 1]),
+            Err(FilterError::Truncated)
+        ));
+        assert!(read(&bytes).is_ok());
+    }
+
+    #[test]
+    fn mismatched_params_are_rejected() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let (filter, _) = filled::<BlanketBBFBlock>(&mut rng);
+        let path = temp_path("params");
+        filter.save(&path).unwrap();
+        let params = filter.params();
+        let mismatches = [
+            BBFParams {
+                k: params.k + 1,
+                ..params
+            },
+            BBFParams {
+                hash_count: params.hash_count % MAX_HASH_COUNT + 1,
+                ..params
+            },
+            BBFParams {
+                seed: params.seed + 1,
+                ..params
+            },
+            BBFParams {
+                block_count: params.block_count + 1,
+                ..params
+            },
+        ];
+        let results = mismatches
+            .iter()
+            .map(|expected| BBFilter::<BlanketBBFBlock>::load_matching(&path, expected))
+            .collect::<Vec<_>>();
+        std::fs::remove_file(&path).unwrap();
+        for (result, expected) in results.into_iter().zip(mismatches) {
+            match result {
+                Err(FilterError::ParamMismatch { expected: e, found }) => {
+                    assert_eq!(e, expected);
+                    assert_eq!(found, params);
+                }
+                other => panic!("Expected a parameter mismatch, got {:?}", other.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn mismatched_layout_is_rejected() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let (filter, _) = filled::<BlanketBBFBlock>(&mut rng);
+        let mut bytes = Vec::new();
+        filter.write_to(&mut bytes).unwrap();
+
+        let mut layout = bytes.clone();
+        layout[12..16].copy_from_slice(&(BlanketBBFBlock::LAYOUT + 7).to_le_bytes());
+        assert!(matches!(
+            BBFilter::<BlanketBBFBlock>::read_from(&layout[..], None),
+            Err(FilterError::LayoutMismatch {
+                expected: 1,
+                found: 8
+            })
+        ));
+
+        let mut words = bytes.clone();
+        words[56..64].copy_from_slice(&(BlanketBBFBlock::WORDS as u64 + 1).to_le_bytes());
+        assert!(matches!(
+            BBFilter::<BlanketBBFBlock>::read_from(&words[..], None),
+            Err(FilterError::LayoutMismatch { .. })
+        ));
+
+        #[cfg(target_arch = "x86_64")]
+        assert!(matches!(
+            BBFilter::<crate::filters::blocks::avx2::Avx2BBFBlock>::read_from(&bytes[..], None),
+            Err(FilterError::LayoutMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,778 @@
+/*
+Rank-select quotient filter over k-mers, with counters and doubling resize.
+
+A k-mer hash is cut to a fingerprint of q + r bits. The top q bits pick the
+home slot (quotient), the low r bits are stored (remainder). Remainders of one
+quotient form a run, kept sorted, and runs are laid out in quotient order,
+shifted right of their home slot when needed. Two bit vectors describe them:
+    occupieds   bit x is set when some fingerprint has quotient x
+    runends     bit i is set when slot i ends a run
+Every block of 64 slots records how far runs of earlier quotients spill into
+it, so locating a run takes one rank and one select.
+
+Slots hold r + 1 bits, a remainder or counter digit followed by a flag. A
+remainder slot (flag 0) is followed by the counter slots (flag 1) of its
+element, little endian digits of the count minus one in base 2^r.
+
+Resizing doubles the slots by moving one bit from the remainder to the
+quotient, so fingerprints and therefore k-mers are never rehashed.
+ */
+use std::sync::RwLock;
+
+use crate::structures::{
+    collection::Collection,
+    sequence::{
+        complement::{Complementation, Reversal},
+        packed::{PackedSeq, PackedSeqSlice},
+        storage::Storage,
+    },
+};
+
+use super::{
+    error::FilterError,
+    rolling_hash::{RollingHashExt, RollingHashes},
+};
+
+// Slots are resized past this fraction in use
+const MAX_LOAD: f64 = 0.9;
+// Remainders are never shrunk below this many bits by resizing
+const MIN_REMAINDER_BITS: u32 = 2;
+// Smallest number of quotient bits, a full block
+const MIN_QUOTIENT_BITS: u32 = 6;
+
+// Parameters two filters must share to be merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QFParams {
+    pub k: usize,
+    pub fingerprint_bits: u32,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct QFBlock {
+    offset: u32,
+    occupieds: u64,
+    runends: u64,
+}
+
+struct QFTable {
+    quotient_bits: u32,
+    remainder_bits: u32,
+    // Home slots, followed by room for runs spilling past the last one
+    slot_count: usize,
+    blocks: Vec<QFBlock>,
+    slots: Vec<u64>,
+    // Distinct fingerprints
+    len: usize,
+    // Slots in use, remainders and counters
+    used: usize,
+}
+
+// Position of the nth set bit of a word, counting from zero
+#[inline]
+fn select_word(mut word: u64, n: u32) -> u32 {
+    for _ in 0..n {
+        word &= word - 1;
+    }
+    word.trailing_zeros()
+}
+
+// Bits [0, i] of a word
+#[inline]
+fn low_bits(i: usize) -> u64 {
+    if i >= 63 {
+        u64::MAX
+    } else {
+        (1 << (i + 1)) - 1
+    }
+}
+
+impl QFTable {
+    fn new(quotient_bits: u32, remainder_bits: u32) -> Self {
+        let home = 1usize << quotient_bits;
+        let slot_count = (home + home / 8 + 64).next_multiple_of(64);
+        let words = (slot_count * (remainder_bits as usize + 1)).div_ceil(64) + 1;
+        Self {
+            quotient_bits,
+            remainder_bits,
+            slot_count,
+            blocks: vec![QFBlock::default(); slot_count / 64],
+            slots: vec![0; words],
+            len: 0,
+            used: 0,
+        }
+    }
+
+    #[inline]
+    fn slot_width(&self) -> usize {
+        self.remainder_bits as usize + 1
+    }
+
+    #[inline]
+    fn get(&self, i: usize) -> u64 {
+        let width = self.slot_width();
+        let (word, shift) = ((i * width) / 64, (i * width) % 64);
+        let mut value = self.slots[word] >> shift;
+        if shift + width > 64 {
+            value |= self.slots[word + 1] << (64 - shift);
+        }
+        value & low_bits(width - 1)
+    }
+
+    #[inline]
+    fn set(&mut self, i: usize, value: u64) {
+        let width = self.slot_width();
+        let mask = low_bits(width - 1);
+        let (word, shift) = ((i * width) / 64, (i * width) % 64);
+        self.slots[word] = (self.slots[word] & !(mask << shift)) | (value << shift);
+        if shift + width > 64 {
+            let high = 64 - shift;
+            self.slots[word + 1] = (self.slots[word + 1] & !(mask >> high)) | (value >> high);
+        }
+    }
+
+    #[inline]
+    fn is_occupied(&self, x: usize) -> bool {
+        self.blocks[x / 64].occupieds >> (x % 64) & 1 != 0
+    }
+
+    #[inline]
+    fn is_runend(&self, i: usize) -> bool {
+        self.blocks[i / 64].runends >> (i % 64) & 1 != 0
+    }
+
+    #[inline]
+    fn set_runend(&mut self, i: usize, value: bool) {
+        let block = &mut self.blocks[i / 64];
+        block.runends = (block.runends & !(1 << (i % 64))) | ((value as u64) << (i % 64));
+    }
+
+    // First slot after the runs of every quotient up to and including x
+    // Only an upper bound on where they end when none of them spill into x's block.
+    fn run_limit(&self, x: usize) -> usize {
+        let b = x / 64;
+        let block = &self.blocks[b];
+        let start = 64 * b + block.offset as usize;
+        let mut rank = (block.occupieds & low_bits(x % 64)).count_ones();
+        if rank == 0 {
+            return start;
+        }
+        // Select the rank-th runend at or after start
+        let mut word = start / 64;
+        let mut bits = self.blocks[word].runends & (u64::MAX << (start % 64));
+        loop {
+            let ones = bits.count_ones();
+            if ones >= rank {
+                return 64 * word + select_word(bits, rank - 1) as usize + 1;
+            }
+            rank -= ones;
+            word += 1;
+            bits = self.blocks[word].runends;
+        }
+    }
+
+    // First slot of the run for quotient x
+    #[inline]
+    fn run_start(&self, x: usize) -> usize {
+        if x == 0 {
+            0
+        } else {
+            self.run_limit(x - 1).max(x)
+        }
+    }
+
+    fn find_empty(&self, mut i: usize) -> Option<usize> {
+        while i < self.slot_count {
+            let limit = self.run_limit(i);
+            if limit <= i {
+                return Some(i);
+            }
+            i = limit;
+        }
+        None
+    }
+
+    // Insert one slot at pos, shifting later slots up to the next empty one
+    // The caller fixes up occupieds, then offsets with the returned empty slot.
+    fn insert_slot(&mut self, pos: usize, value: u64, runend: bool) -> Result<usize, FilterError> {
+        let empty = self.find_empty(pos).ok_or(FilterError::Full)?;
+        for i in (pos + 1..=empty).rev() {
+            let (prev, prev_end) = (self.get(i - 1), self.is_runend(i - 1));
+            self.set(i, prev);
+            self.set_runend(i, prev_end);
+        }
+        self.set(pos, value);
+        self.set_runend(pos, runend);
+        self.used += 1;
+        Ok(empty)
+    }
+
+    // Recompute the offsets of blocks starting in (x, empty + 1]
+    fn fix_offsets(&mut self, x: usize, empty: usize) {
+        let last = ((empty + 1) / 64).min(self.blocks.len() - 1);
+        for b in x / 64 + 1..=last {
+            self.blocks[b].offset = self.run_limit(64 * b - 1).saturating_sub(64 * b) as u32;
+        }
+    }
+
+    // Insert a slot inside the run of x
+    fn insert_within(&mut self, x: usize, pos: usize, value: u64) -> Result<(), FilterError> {
+        let empty = self.insert_slot(pos, value, false)?;
+        self.fix_offsets(x, empty);
+        Ok(())
+    }
+
+    // Insert a slot right after the run of x, which moves its runend
+    fn append(&mut self, x: usize, pos: usize, value: u64) -> Result<(), FilterError> {
+        let empty = self.insert_slot(pos, value, true)?;
+        self.set_runend(pos - 1, false);
+        self.fix_offsets(x, empty);
+        Ok(())
+    }
+
+    // Start the run of x with a single slot
+    fn start_run(&mut self, x: usize, pos: usize, value: u64) -> Result<(), FilterError> {
+        let empty = self.insert_slot(pos, value, true)?;
+        self.blocks[x / 64].occupieds |= 1 << (x % 64);
+        self.fix_offsets(x, empty);
+        Ok(())
+    }
+
+    #[inline]
+    fn split(&self, fingerprint: u64) -> (usize, u64) {
+        (
+            (fingerprint >> self.remainder_bits) as usize,
+            fingerprint & low_bits(self.remainder_bits as usize - 1),
+        )
+    }
+
+    // Number of counter slots following the remainder at pos, and the count they encode
+    fn read_counter(&self, pos: usize, end: usize) -> (usize, u64) {
+        let mut digits = 0;
+        let mut extra = 0u64;
+        while pos + digits < end && self.get(pos + digits + 1) & 1 == 1 {
+            digits += 1;
+            let digit = self.get(pos + digits) >> 1;
+            extra |= digit
+                .checked_shl(self.remainder_bits * (digits as u32 - 1))
+                .unwrap_or(0);
+        }
+        (digits, extra + 1)
+    }
+
+    // Counter digits of a count, empty for a count of one
+    fn counter_digits(&self, count: u64) -> Vec<u64> {
+        let mut rest = count - 1;
+        let mut digits = Vec::new();
+        while rest > 0 {
+            digits.push((rest & low_bits(self.remainder_bits as usize - 1)) << 1 | 1);
+            rest >>= self.remainder_bits;
+        }
+        digits
+    }
+
+    // Locate a remainder in the run of x, as (position, counter slots, count)
+    // or the position it would be inserted at.
+    fn find(&self, x: usize, remainder: u64) -> Result<(usize, usize, u64), usize> {
+        let start = self.run_start(x);
+        if !self.is_occupied(x) {
+            return Err(start);
+        }
+        let end = self.run_limit(x) - 1;
+        let mut pos = start;
+        while pos <= end {
+            let stored = self.get(pos) >> 1;
+            let (digits, count) = self.read_counter(pos, end);
+            if stored == remainder {
+                return Ok((pos, digits, count));
+            }
+            if stored > remainder {
+                return Err(pos);
+            }
+            pos += digits + 1;
+        }
+        Err(end + 1)
+    }
+
+    fn count(&self, fingerprint: u64) -> u64 {
+        let (x, remainder) = self.split(fingerprint);
+        match self.find(x, remainder) {
+            Ok((_, _, count)) => count,
+            Err(_) => 0,
+        }
+    }
+
+    // Add count occurrences of a fingerprint, returning its previous count
+    fn insert(&mut self, fingerprint: u64, count: u64) -> Result<u64, FilterError> {
+        // Zero has no counter encoding, and adding nothing leaves the table unchanged
+        if count == 0 {
+            return Ok(self.count(fingerprint));
+        }
+        let (x, remainder) = self.split(fingerprint);
+        match self.find(x, remainder) {
+            Ok((pos, digits, old)) => {
+                let new = self.counter_digits(old.saturating_add(count));
+                let last = self.is_runend(pos + digits);
+                for (i, digit) in new.iter().enumerate().skip(digits) {
+                    if last {
+                        self.append(x, pos + i + 1, *digit)?;
+                    } else {
+                        self.insert_within(x, pos + i + 1, *digit)?;
+                    }
+                }
+                for (i, digit) in new.iter().enumerate() {
+                    self.set(pos + i + 1, *digit);
+                }
+                Ok(old)
+            }
+            Err(pos) => {
+                let digits = self.counter_digits(count);
+                let appending = if self.is_occupied(x) {
+                    let appending = pos == self.run_limit(x);
+                    if appending {
+                        self.append(x, pos, remainder << 1)?;
+                    } else {
+                        self.insert_within(x, pos, remainder << 1)?;
+                    }
+                    appending
+                } else {
+                    self.start_run(x, pos, remainder << 1)?;
+                    true
+                };
+                for (i, digit) in digits.iter().enumerate() {
+                    if appending {
+                        self.append(x, pos + i + 1, *digit)?;
+                    } else {
+                        self.insert_within(x, pos + i + 1, *digit)?;
+                    }
+                }
+                self.len += 1;
+                Ok(0)
+            }
+        }
+    }
+
+    // Every fingerprint and its count, in increasing fingerprint order
+    fn entries(&self) -> Vec<(u64, u64)> {
+        let mut entries = Vec::with_capacity(self.len);
+        let mut pos = 0;
+        for x in 0..1usize << self.quotient_bits {
+            if !self.is_occupied(x) {
+                continue;
+            }
+            pos = pos.max(x);
+            let mut end = pos;
+            while !self.is_runend(end) {
+                end += 1;
+            }
+            while pos <= end {
+                let (digits, count) = self.read_counter(pos, end);
+                let remainder = self.get(pos) >> 1;
+                entries.push((((x as u64) << self.remainder_bits) | remainder, count));
+                pos += digits + 1;
+            }
+        }
+        entries
+    }
+
+    // Too full for another element, by load or by runs reaching the last block
+    // An element with the widest counter needs less than a block.
+    #[inline]
+    fn is_overloaded(&self) -> bool {
+        let home = 1usize << self.quotient_bits;
+        self.used as f64 >= MAX_LOAD * home as f64
+            || self.run_limit(home - 1) + 64 > self.slot_count
+    }
+}
+
+pub struct QuotientFilter {
+    table: RwLock<QFTable>,
+    k: usize,
+    seed: u64,
+}
+
+impl QuotientFilter {
+    // Filter with 2^quotient_bits home slots and remainders of remainder_bits
+    pub fn new(k: usize, quotient_bits: u32, remainder_bits: u32) -> Self {
+        assert!(
+            quotient_bits >= MIN_QUOTIENT_BITS,
+            "At least {} quotient bits are needed",
+            MIN_QUOTIENT_BITS
+        );
+        assert!(
+            remainder_bits >= MIN_REMAINDER_BITS && quotient_bits + remainder_bits <= 64,
+            "Remainders need at least {} bits, and fingerprints at most 64",
+            MIN_REMAINDER_BITS
+        );
+        Self {
+            table: RwLock::new(QFTable::new(quotient_bits, remainder_bits)),
+            k,
+            seed: 0,
+        }
+    }
+
+    // Size for `expected_kmers` distinct k-mers at a target false positive rate
+    // Every doubling past the expected size doubles the rate.
+    pub fn with_fpr(k: usize, expected_kmers: usize, fpr: f64) -> Self {
+        assert!(
+            fpr > 0.0 && fpr < 1.0,
+            "False positive rate must be in (0, 1)"
+        );
+        let quotient_bits =
+            ((expected_kmers as f64 / MAX_LOAD).log2().ceil() as u32).max(MIN_QUOTIENT_BITS);
+        let remainder_bits =
+            ((1.0 / fpr).log2().ceil() as u32).clamp(MIN_REMAINDER_BITS, 64 - quotient_bits);
+        Self::new(k, quotient_bits, remainder_bits)
+    }
+
+    // Hash with the given seed instead of unseeded hashing
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    pub fn params(&self) -> QFParams {
+        let table = self.table.read().unwrap();
+        QFParams {
+            k: self.k,
+            fingerprint_bits: table.quotient_bits + table.remainder_bits,
+            seed: self.seed,
+        }
+    }
+
+    pub fn quotient_bits(&self) -> u32 {
+        self.table.read().unwrap().quotient_bits
+    }
+
+    pub fn remainder_bits(&self) -> u32 {
+        self.table.read().unwrap().remainder_bits
+    }
+
+    // Number of distinct fingerprints
+    pub fn len(&self) -> usize {
+        self.table.read().unwrap().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        let table = self.table.read().unwrap();
+        table.used as f64 / (1usize << table.quotient_bits) as f64
+    }
+
+    // Chance that an absent k-mer shares a fingerprint with a stored one
+    pub fn expected_fpr(&self) -> f64 {
+        let table = self.table.read().unwrap();
+        let fingerprints = 2f64.powi((table.quotient_bits + table.remainder_bits) as i32);
+        1.0 - (-(table.len as f64) / fingerprints).exp()
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        let table = self.table.read().unwrap();
+        table.slots.len() * 8 + table.blocks.len() * std::mem::size_of::<QFBlock>()
+    }
+
+    #[inline]
+    fn fingerprint(&self, table: &QFTable, hash: usize) -> u64 {
+        (hash as u64) >> (64 - table.quotient_bits - table.remainder_bits)
+    }
+
+    // Add count occurrences of a hashed k-mer, growing the filter when needed
+    // Returns the previous count.
+    pub fn insert_hash(&self, hash: usize, count: u64) -> Result<u64, FilterError> {
+        let mut table = self.table.write().unwrap();
+        if table.is_overloaded() {
+            Self::grow(&mut table)?;
+        }
+        let fingerprint = self.fingerprint(&table, hash);
+        table.insert(fingerprint, count)
+    }
+
+    pub fn count_hash(&self, hash: usize) -> u64 {
+        let table = self.table.read().unwrap();
+        table.count(self.fingerprint(&table, hash))
+    }
+
+    // Double the home slots, moving one remainder bit into the quotient
+    fn grow(table: &mut QFTable) -> Result<(), FilterError> {
+        if table.remainder_bits <= MIN_REMAINDER_BITS {
+            return Err(FilterError::Full);
+        }
+        let mut grown = QFTable::new(table.quotient_bits + 1, table.remainder_bits - 1);
+        for (fingerprint, count) in table.entries() {
+            grown.insert(fingerprint, count)?;
+        }
+        *table = grown;
+        Ok(())
+    }
+
+    // Explicitly double the filter
+    pub fn resize(&self) -> Result<(), FilterError> {
+        Self::grow(&mut self.table.write().unwrap())
+    }
+
+    // Every fingerprint and its count, in increasing fingerprint order
+    pub fn entries(&self) -> Vec<(u64, u64)> {
+        self.table.read().unwrap().entries()
+    }
+
+    // Filter holding the k-mers of both, with counts summed
+    pub fn merge(&self, other: &Self) -> Result<Self, FilterError> {
+        if self.params() != other.params() {
+            return Err(FilterError::QFParamMismatch {
+                expected: self.params(),
+                found: other.params(),
+            });
+        }
+        let (a, b) = (self.table.read().unwrap(), other.table.read().unwrap());
+        let fingerprint_bits = a.quotient_bits + a.remainder_bits;
+        let needed = ((a.used + b.used) as f64 / MAX_LOAD).log2().ceil() as u32;
+        let quotient_bits = needed
+            .max(a.quotient_bits)
+            .max(b.quotient_bits)
+            .min(fingerprint_bits - MIN_REMAINDER_BITS);
+        let mut table = QFTable::new(quotient_bits, fingerprint_bits - quotient_bits);
+
+        let (a, b) = (a.entries(), b.entries());
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            let next = match (a.get(i), b.get(j)) {
+                (Some(x), Some(y)) if x.0 == y.0 => {
+                    i += 1;
+                    j += 1;
+                    (x.0, x.1.saturating_add(y.1))
+                }
+                (Some(x), Some(y)) if x.0 < y.0 => {
+                    i += 1;
+                    *x
+                }
+                (Some(x), None) => {
+                    i += 1;
+                    *x
+                }
+                (_, Some(y)) => {
+                    j += 1;
+                    *y
+                }
+                (None, None) => unreachable!(),
+            };
+            table.insert(next.0, next.1)?;
+        }
+        Ok(Self {
+            table: RwLock::new(table),
+            k: self.k,
+            seed: self.seed,
+        })
+    }
+
+    pub fn insert_kmers<T, R, C>(&self, seq: &PackedSeq<T, R, C>) -> Result<(), FilterError>
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        if seq.len() < self.k {
+            return Ok(());
+        }
+        for hashes in seq.rolling_hash_iter(self.k, 1, self.seed) {
+            self.insert_hash(hashes[0], 1)?;
+        }
+        Ok(())
+    }
+
+    // Estimated number of occurrences, exact unless fingerprints collide
+    pub fn count_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> u64
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.k);
+        self.count_hash(RollingHashes::from_kmer(&kmer, 1, self.seed)[0])
+    }
+
+    pub fn contains_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        self.count_kmer(kmer) > 0
+    }
+}
+
+impl<'a, T, R, C> Collection<PackedSeqSlice<'a, T, R, C>> for QuotientFilter
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    type Error = FilterError;
+
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn insert(&self, x: PackedSeqSlice<'a, T, R, C>) -> Result<bool, FilterError> {
+        debug_assert_eq!(x.len, self.k);
+        Ok(self.insert_hash(RollingHashes::from_kmer(&x, 1, self.seed)[0], 1)? > 0)
+    }
+
+    fn contains(&self, x: PackedSeqSlice<'a, T, R, C>) -> bool {
+        self.contains_kmer(x)
+    }
+
+    fn len_estimate(&self) -> usize {
+        self.len()
+    }
+
+    fn fpr(&self) -> f64 {
+        self.expected_fpr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    const FINGERPRINT_BITS: u32 = 32;
+
+    // Hash whose fingerprint in the filter is fp, the bits below it being ignored
+    fn hash_of(filter: &QuotientFilter, fp: u64, rng: &mut StdRng) -> usize {
+        let low = 64 - filter.quotient_bits() - filter.remainder_bits();
+        ((fp << low) | rng.gen_range(0..1u64 << low)) as usize
+    }
+
+    fn random_count(rng: &mut StdRng) -> u64 {
+        match rng.gen_range(0..10) {
+            0 => 0,
+            1..=6 => 1,
+            7 | 8 => rng.gen_range(2..300),
+            _ => rng.gen_range(0..1 << 40),
+        }
+    }
+
+    // Apply random insertions to a filter and a model, checking previous counts
+    fn fill(
+        filter: &QuotientFilter,
+        model: &mut HashMap<u64, u64>,
+        pool: &[u64],
+        ops: usize,
+        rng: &mut StdRng,
+    ) {
+        for op in 0..ops {
+            let fp = pool[rng.gen_range(0..pool.len())];
+            let count = random_count(rng);
+            let previous = filter.insert_hash(hash_of(filter, fp, rng), count).unwrap();
+            assert_eq!(previous, model.get(&fp).copied().unwrap_or(0));
+            if count > 0 {
+                let entry = model.entry(fp).or_default();
+                *entry = entry.saturating_add(count);
+            }
+            if op % 1000 == 999 {
+                check(filter, model, rng);
+            }
+        }
+    }
+
+    fn check(filter: &QuotientFilter, model: &HashMap<u64, u64>, rng: &mut StdRng) {
+        let mut expected: Vec<(u64, u64)> = model.iter().map(|(fp, c)| (*fp, *c)).collect();
+        expected.sort_unstable();
+        assert_eq!(filter.entries(), expected);
+        assert_eq!(filter.len(), model.len());
+        for (fp, count) in model {
+            assert_eq!(filter.count_hash(hash_of(filter, *fp, rng)), *count);
+        }
+    }
+
+    #[test]
+    fn counts_match_hashmap() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let filter =
+            QuotientFilter::new(21, MIN_QUOTIENT_BITS, FINGERPRINT_BITS - MIN_QUOTIENT_BITS);
+        let pool: Vec<u64> = (0..3000)
+            .map(|_| rng.gen_range(0..1 << FINGERPRINT_BITS))
+            .collect();
+        let mut model = HashMap::new();
+        for _ in 0..3 {
+            fill(&filter, &mut model, &pool, 4000, &mut rng);
+            filter.resize().unwrap();
+            check(&filter, &model, &mut rng);
+        }
+    }
+
+    #[test]
+    fn narrow_remainders() {
+        // Resizing down to two bit remainders, with counters of many digits
+        let mut rng = StdRng::seed_from_u64(1);
+        let filter = QuotientFilter::new(21, MIN_QUOTIENT_BITS, 8);
+        while filter.remainder_bits() > MIN_REMAINDER_BITS {
+            filter.resize().unwrap();
+        }
+        assert!(matches!(filter.resize(), Err(FilterError::Full)));
+        // Room for every element with the widest counter, 20 two bit digits
+        let pool: Vec<u64> = (0..150).map(|_| rng.gen_range(0..1 << 14)).collect();
+        let mut model = HashMap::new();
+        fill(&filter, &mut model, &pool, 3000, &mut rng);
+        check(&filter, &model, &mut rng);
+    }
+
+    #[test]
+    fn zero_count() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let filter = QuotientFilter::new(21, 8, 24);
+        let hash = hash_of(&filter, 12345, &mut rng);
+        assert_eq!(filter.insert_hash(hash, 0).unwrap(), 0);
+        assert!(filter.is_empty());
+        filter.insert_hash(hash, 3).unwrap();
+        assert_eq!(filter.insert_hash(hash, 0).unwrap(), 3);
+        assert_eq!(filter.entries(), vec![(12345, 3)]);
+    }
+
+    #[test]
+    fn merge_sums_counts() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let pool: Vec<u64> = (0..2000)
+            .map(|_| rng.gen_range(0..1 << FINGERPRINT_BITS))
+            .collect();
+        let (a, b) = (
+            QuotientFilter::new(21, MIN_QUOTIENT_BITS, FINGERPRINT_BITS - MIN_QUOTIENT_BITS),
+            QuotientFilter::new(21, 10, FINGERPRINT_BITS - 10),
+        );
+        let (mut model_a, mut model_b) = (HashMap::new(), HashMap::new());
+        fill(&a, &mut model_a, &pool[..1500], 3000, &mut rng);
+        fill(&b, &mut model_b, &pool[500..], 3000, &mut rng);
+        b.resize().unwrap();
+
+        let merged = a.merge(&b).unwrap();
+        let mut model = model_a;
+        for (fp, count) in model_b {
+            let entry = model.entry(fp).or_default();
+            *entry = entry.saturating_add(count);
+        }
+        check(&merged, &model, &mut rng);
+        fill(&merged, &mut model, &pool, 2000, &mut rng);
+
+        let other = QuotientFilter::new(21, 8, 16);
+        assert!(matches!(
+            a.merge(&other),
+            Err(FilterError::QFParamMismatch { .. })
+        ));
+    }
+}
@@ -1,54 +1,114 @@
 //
 
+use std::ops::Deref;
+
 use crate::structures::sequence::{
     complement::{Complementation, Reversal},
     packed::{PackedSeq, PackedSeqSlice},
     storage::Storage,
 };
+use crate::utils::hash::mix64;
+
+use super::MAX_HASH_COUNT;
 
-use super::HASH_COUNT;
+// Salt for the second hash of the double hashing scheme
+const STEP_SALT: u64 = 0x94d049bb133111eb;
+
+// Hashes of a single k-mer, one per hash function
+// Stored inline, up to MAX_HASH_COUNT
+#[derive(Debug, Clone, Copy)]
+pub struct RollingHashes {
+    hashes: [usize; MAX_HASH_COUNT],
+    len: usize,
+}
 
-pub type RollingHashes = [usize; HASH_COUNT];
+impl RollingHashes {
+    // Kirsch-Mitzenmacher double hashing from the packed k-mer
+    // A seed of zero leaves the k-mer unsalted.
+    #[inline]
+    pub fn from_packed(kmer: usize, hash_count: usize, seed: u64) -> Self {
+        debug_assert!((1..=MAX_HASH_COUNT).contains(&hash_count));
+        let kmer = kmer as u64 ^ mix64(seed);
+        let h1 = mix64(kmer) as usize;
+        let h2 = mix64(kmer ^ STEP_SALT) as usize | 1;
+        let mut hashes = [0; MAX_HASH_COUNT];
+        for (i, hash) in hashes.iter_mut().enumerate().take(hash_count) {
+            *hash = h1.wrapping_add(i.wrapping_mul(h2));
+        }
+        Self {
+            hashes,
+            len: hash_count,
+        }
+    }
+}
+
+impl Deref for RollingHashes {
+    type Target = [usize];
+
+    #[inline]
+    fn deref(&self) -> &[usize] {
+        &self.hashes[..self.len]
+    }
+}
+
+#[inline]
+fn kmer_mask(k: usize) -> usize {
+    if 2 * k >= usize::BITS as usize {
+        usize::MAX
+    } else {
+        (1 << (2 * k)) - 1
+    }
+}
 
 pub trait RollingHashExt<'a, T: Storage, R: Reversal, C: Complementation> {
-    fn from_kmer(kmer: &PackedSeqSlice<'a, T, R, C>) -> RollingHashes;
+    fn from_kmer(kmer: &PackedSeqSlice<'a, T, R, C>, hash_count: usize, seed: u64)
+        -> RollingHashes;
 }
 
 impl<'a, T: Storage, R: Reversal, C: Complementation> RollingHashExt<'a, T, R, C>
     for RollingHashes
 {
-    fn from_kmer(kmer: &PackedSeqSlice<'a, T, R, C>) -> RollingHashes {
-        let acc = (0..kmer.len).into_iter().fold(0, |acc: usize, i| {
+    fn from_kmer(
+        kmer: &PackedSeqSlice<'a, T, R, C>,
+        hash_count: usize,
+        seed: u64,
+    ) -> RollingHashes {
+        let acc = (0..kmer.len).fold(0, |acc: usize, i| {
             acc.wrapping_mul(4).wrapping_add(kmer.get(i) as usize)
         });
-        let mask = (1 << 2 * kmer.len) - 1;
-        [acc & mask; HASH_COUNT]
+        RollingHashes::from_packed(acc & kmer_mask(kmer.len), hash_count, seed)
     }
 }
 
 // Iterator over fingerprints of all k-mers in a sequence slice
-// Hashes should correspond to the 8-mer maximizer of the k-mer
 #[derive(Debug)]
 pub struct RollingHashIter<'a, T: Storage, R: Reversal, C: Complementation> {
     data: PackedSeqSlice<'a, T, R, C>,
     pos: usize,
-    window_size: usize,
+    hash_count: usize,
+    seed: u64,
     buffer: usize,
     mask: usize,
 }
 
 impl<'a, T: Storage, R: Reversal, C: Complementation> RollingHashIter<'a, T, R, C> {
-    pub fn new(data: PackedSeqSlice<'a, T, R, C>, window_size: usize) -> Self {
-        let acc = (0..window_size - 1).into_iter().fold(0, |acc: usize, i| {
+    pub fn new(
+        data: PackedSeqSlice<'a, T, R, C>,
+        window_size: usize,
+        hash_count: usize,
+        seed: u64,
+    ) -> Self {
+        let acc = (0..window_size - 1).fold(0, |acc: usize, i| {
             acc.wrapping_mul(4).wrapping_add(data.get(i) as usize)
         });
 
         Self {
             data,
             pos: window_size - 1,
-            window_size,
+            hash_count,
+            seed,
             buffer: acc,
-            mask: (1 << 2 * window_size) - 1,
+            mask: kmer_mask(window_size),
         }
     }
 }
@@ -57,9 +117,7 @@ impl<'a, T: Storage, R: Reversal, C: Complementation> Iterator for RollingHashIt
     type Item = RollingHashes;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // println!("Iter: {}", self.pos);
         if self.pos >= self.data.len {
-            println!("A {}", self.pos);
             return None;
         }
         self.buffer = self
@@ -69,13 +127,11 @@ impl<'a, T: Storage, R: Reversal, C: Complementation> Iterator for RollingHashIt
         self.buffer &= self.mask;
         self.pos += 1;
 
-        println!(
-            "Bucket Hash {:?} {:b}",
-            self.data.get(self.pos - 1),
-            self.buffer
-        );
-
-        Some([self.buffer; HASH_COUNT])
+        Some(RollingHashes::from_packed(
+            self.buffer,
+            self.hash_count,
+            self.seed,
+        ))
     }
 }
 
@@ -85,7 +141,45 @@ where
     R: Reversal,
     C: Complementation,
 {
-    pub fn rolling_hash_iter(&self, window_size: usize) -> RollingHashIter<T, R, C> {
-        RollingHashIter::new(self.as_slice(), window_size)
+    pub fn rolling_hash_iter(
+        &self,
+        window_size: usize,
+        hash_count: usize,
+        seed: u64,
+    ) -> RollingHashIter<'_, T, R, C> {
+        RollingHashIter::new(self.as_slice(), window_size, hash_count, seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::complement::{Forward, Identity};
+
+    #[test]
+    fn iterator_matches_from_kmer() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let seq = PackedSeq::<u64, Forward, Identity>::random(500, &mut rng);
+        for k in [5, 21, 31, 32] {
+            for hash_count in 1..=MAX_HASH_COUNT {
+                let hashes = seq.rolling_hash_iter(k, hash_count, 3).collect::<Vec<_>>();
+                assert_eq!(hashes.len(), seq.len() - k + 1);
+                for (i, rolled) in hashes.iter().enumerate() {
+                    let direct = RollingHashes::from_kmer(&seq.slice(i, k), hash_count, 3);
+                    assert_eq!(rolled.len(), hash_count);
+                    assert_eq!(**rolled, *direct);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hashes_extend_with_count() {
+        let fewer = RollingHashes::from_packed(0x1234, 3, 9);
+        let more = RollingHashes::from_packed(0x1234, MAX_HASH_COUNT, 9);
+        assert_eq!(*fewer, more[..3]);
+        assert_ne!(*RollingHashes::from_packed(0x1234, 3, 10), *fewer);
     }
 }
@@ -0,0 +1,228 @@
+/*
+Empirical validation of BBFilter configurations.
+
+The filter is queried with k-mers known to be absent, every hit is a false
+positive. The model rate takes block loads as Poisson around the mean, which
+clumping of k-mers by minimizer can exceed, so the observed rate is reported
+next to the model at the mean load and the model averaged over the actual
+block densities. The gap between the last two is the cost of load imbalance.
+ */
+use rand::Rng;
+
+use crate::structures::sequence::{
+    complement::{Complementation, Forward, Identity, Reversal},
+    packed::PackedSeq,
+    storage::Storage,
+};
+
+use super::{
+    blocks::BBFBlock,
+    bloom::{filter_fpr, BBFilter, NativeBBFilter},
+};
+
+// Distribution of the number of keys written to each block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DensityStats {
+    pub min: u32,
+    pub mean: f64,
+    pub max: u32,
+    pub std_dev: f64,
+}
+
+impl DensityStats {
+    // Fullest block relative to the mean, 1 when perfectly balanced
+    pub fn imbalance(&self) -> f64 {
+        if self.mean == 0.0 {
+            1.0
+        } else {
+            self.max as f64 / self.mean
+        }
+    }
+
+    // Coefficient of variation of block densities
+    pub fn dispersion(&self) -> f64 {
+        if self.mean == 0.0 {
+            0.0
+        } else {
+            self.std_dev / self.mean
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FprReport {
+    // Number of absent k-mers queried
+    pub queries: usize,
+    pub false_positives: usize,
+    pub observed_fpr: f64,
+    // Model at the mean density
+    pub model_fpr: f64,
+    // Model averaged over the actual block densities
+    pub density_fpr: f64,
+    pub density: DensityStats,
+}
+
+impl FprReport {
+    // Binomial standard error of the observed rate
+    pub fn std_error(&self) -> f64 {
+        (self.observed_fpr * (1.0 - self.observed_fpr) / self.queries as f64).sqrt()
+    }
+
+    // Observed over modelled rate, above 1 when the filter does worse than predicted
+    pub fn excess(&self) -> f64 {
+        self.observed_fpr / self.model_fpr
+    }
+}
+
+impl<B: BBFBlock> BBFilter<B> {
+    pub fn density_stats(&self) -> DensityStats {
+        let blocks = self.blocks();
+        let n = blocks.len() as f64;
+        let (mut min, mut max, mut sum, mut sum_sq) = (u32::MAX, 0, 0.0, 0.0);
+        for block in blocks {
+            let d = block.get_density();
+            min = min.min(d);
+            max = max.max(d);
+            sum += d as f64;
+            sum_sq += d as f64 * d as f64;
+        }
+        let mean = sum / n;
+        DensityStats {
+            min,
+            mean,
+            max,
+            std_dev: (sum_sq / n - mean * mean).max(0.0).sqrt(),
+        }
+    }
+
+    // Query every k-mer of `absent`, all of which must be missing from the filter
+    pub fn measure_fpr<T, R, C>(&self, absent: &PackedSeq<T, R, C>) -> FprReport
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        assert!(absent.len() >= self.k(), "Need at least one k-mer to query");
+        let queries = absent.len() - self.k() + 1;
+        let false_positives = absent
+            .bucket_hash_iter(self.k(), self.seed())
+            .zip(absent.rolling_hash_iter(self.k(), self.hash_count(), self.seed()))
+            .filter(|(bhashes, hashes)| self.contains_hashes(*bhashes, hashes))
+            .count();
+
+        let density = self.density_stats();
+        // A query reads two blocks picked independently of their load
+        let block_mean = self
+            .blocks()
+            .iter()
+            .map(|b| B::block_fpr(b.get_density() as f64, self.hash_count()))
+            .sum::<f64>()
+            / self.block_count() as f64;
+        FprReport {
+            queries,
+            false_positives,
+            observed_fpr: false_positives as f64 / queries as f64,
+            model_fpr: filter_fpr::<B>(density.mean, self.hash_count()),
+            density_fpr: 1.0 - (1.0 - block_mean).powi(2),
+            density,
+        }
+    }
+
+    // measure_fpr over the k-mers of a random sequence.
+    // Random k-mers collide with inserted ones with probability len / 4^k, negligible for large k.
+    pub fn measure_random_fpr<G: Rng>(&self, queries: usize, rng: &mut G) -> FprReport {
+        assert!(queries > 0, "Need at least one k-mer to query");
+        let absent = PackedSeq::<u64, Forward, Identity>::random(queries + self.k() - 1, rng);
+        self.measure_fpr(&absent)
+    }
+}
+
+impl NativeBBFilter {
+    pub fn density_stats(&self) -> DensityStats {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            NativeBBFilter::Avx2(f) => f.density_stats(),
+            NativeBBFilter::Blanket(f) => f.density_stats(),
+        }
+    }
+
+    pub fn measure_fpr<T, R, C>(&self, absent: &PackedSeq<T, R, C>) -> FprReport
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            NativeBBFilter::Avx2(f) => f.measure_fpr(absent),
+            NativeBBFilter::Blanket(f) => f.measure_fpr(absent),
+        }
+    }
+
+    pub fn measure_random_fpr<G: Rng>(&self, queries: usize, rng: &mut G) -> FprReport {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            NativeBBFilter::Avx2(f) => f.measure_random_fpr(queries, rng),
+            NativeBBFilter::Blanket(f) => f.measure_random_fpr(queries, rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::filters::blocks::blanket::BlanketBBFBlock;
+
+    const K: usize = 21;
+
+    type Seq = PackedSeq<u64, Forward, Identity>;
+
+    fn filled(rng: &mut StdRng) -> (BBFilter<BlanketBBFBlock>, Seq) {
+        let seq = Seq::random(20_000, rng);
+        let filter = BBFilter::<BlanketBBFBlock>::with_fpr(K, seq.len(), 1e-2);
+        filter.insert_kmers(&seq);
+        (filter, seq)
+    }
+
+    #[test]
+    fn inserted_kmers_always_hit() {
+        let (filter, seq) = filled(&mut StdRng::seed_from_u64(1));
+        let report = filter.measure_fpr(&seq);
+        assert_eq!(report.queries, seq.len() - K + 1);
+        assert_eq!(report.false_positives, report.queries);
+    }
+
+    #[test]
+    fn densities_sum_to_len_estimate() {
+        let (filter, _) = filled(&mut StdRng::seed_from_u64(2));
+        let density = filter.density_stats();
+        let total = density.mean * filter.block_count() as f64;
+        assert!((total - filter.len_estimate() as f64).abs() < 1e-6 * total);
+        assert!(density.min as f64 <= density.mean && density.mean <= density.max as f64);
+        assert!(density.imbalance() >= 1.0);
+    }
+
+    #[test]
+    fn random_queries_match_model() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let (filter, _) = filled(&mut rng);
+        let report = filter.measure_random_fpr(50_000, &mut rng);
+        assert_eq!(report.queries, 50_000);
+        // Within four standard errors of the model at the actual densities
+        assert!(
+            (report.observed_fpr - report.density_fpr).abs() < 4.0 * report.std_error(),
+            "{:?}",
+            report
+        );
+    }
+
+    #[test]
+    fn empty_filter_is_balanced() {
+        let filter = BBFilter::<BlanketBBFBlock>::with_fpr(K, 1000, 1e-2);
+        let density = filter.density_stats();
+        assert_eq!((density.min, density.max), (0, 0));
+        assert_eq!((density.imbalance(), density.dispersion()), (1.0, 0.0));
+    }
+}
@@ -0,0 +1,163 @@
+use std::{fs::File, marker::PhantomData, path::Path};
+
+use memmap2::Mmap;
+
+use crate::structures::sequence::{
+    complement::{Complementation, Reversal},
+    packed::PackedSeqSlice,
+    storage::Storage,
+};
+
+use super::{
+    blocks::BBFBlock,
+    bloom::BBFParams,
+    bucket_hashes::{BucketHashExt, BucketHashes},
+    error::FilterError,
+    persist::{BBFHeader, HEADER_LEN},
+    rolling_hash::{RollingHashExt, RollingHashes},
+};
+
+// Read-only BBFilter queried in place over a memory-mapped filter file.
+// Pages are shared between every process mapping the same file, and
+// since nothing is written, queries take no locks.
+pub struct BBFilterView<B: BBFBlock> {
+    map: Mmap,
+    header: BBFHeader,
+    _b: PhantomData<B>,
+}
+
+impl<B: BBFBlock> BBFilterView<B> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FilterError> {
+        let file = File::open(path)?;
+        // The file must not be modified while mapped
+        let map = unsafe { Mmap::map(&file)? };
+        let header = BBFHeader::from_bytes(&map)?;
+        header.check_layout::<B>()?;
+        header.check_len(map.len() as u64)?;
+        Ok(Self {
+            map,
+            header,
+            _b: PhantomData,
+        })
+    }
+
+    pub fn open_matching<P: AsRef<Path>>(path: P, params: &BBFParams) -> Result<Self, FilterError> {
+        let view = Self::open(path)?;
+        view.header.check_params(params)?;
+        Ok(view)
+    }
+
+    #[inline]
+    pub fn params(&self) -> BBFParams {
+        self.header.params
+    }
+
+    #[inline]
+    fn block_words(&self, bucket: usize) -> &[u32] {
+        let index = bucket % self.header.params.block_count;
+        let start = HEADER_LEN + index * B::WORDS * 4;
+        let bytes = &self.map[start..start + B::WORDS * 4];
+        // Maps are page aligned and the header keeps words 4 byte aligned
+        let (_, words, _) = unsafe { bytes.align_to::<u32>() };
+        words
+    }
+
+    pub fn contains_kmer<'a, T, R, C>(&self, kmer: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        debug_assert_eq!(kmer.len, self.header.params.k);
+        let params = &self.header.params;
+        let bhash = BucketHashes::from_kmer(&kmer, params.seed);
+
+        let hashes = RollingHashes::from_kmer(&kmer, params.hash_count, params.seed);
+
+        B::read_all_words(self.block_words(bhash.0), &hashes)
+            || B::read_all_words(self.block_words(bhash.1), &hashes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        filters::{blocks::blanket::BlanketBBFBlock, bloom::BBFilter},
+        structures::sequence::{
+            complement::{Forward, Identity},
+            packed::PackedSeq,
+        },
+    };
+
+    const K: usize = 21;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("camilla-view-{}-{}.bbf", name, std::process::id()))
+    }
+
+    // The view answers every inserted and random k-mer as the filter it was saved from
+    fn matches_filter<B: BBFBlock>(name: &str) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let seq = PackedSeq::<u64, Forward, Identity>::random(20_000, &mut rng);
+        let queries = PackedSeq::<u64, Forward, Identity>::random(50_000, &mut rng);
+        let filter = BBFilter::<B>::with_fpr(K, seq.len(), 0.05).with_seed(9);
+        filter.insert_kmers(&seq);
+
+        let path = temp_path(name);
+        filter.save(&path).unwrap();
+        let view = BBFilterView::<B>::open_matching(&path, &filter.params());
+        std::fs::remove_file(&path).unwrap();
+        let view = view.unwrap();
+
+        assert_eq!(view.params(), filter.params());
+        for i in 0..=seq.len() - K {
+            assert!(view.contains_kmer(seq.slice(i, K)));
+        }
+        let mut hits = 0;
+        for i in 0..=queries.len() - K {
+            let present = filter.contains_kmer(queries.slice(i, K));
+            assert_eq!(view.contains_kmer(queries.slice(i, K)), present);
+            hits += present as usize;
+        }
+        // Random k-mers exercise both answers
+        assert!(hits > 0 && hits < queries.len() / 2);
+    }
+
+    #[test]
+    fn blanket_view_matches_filter() {
+        matches_filter::<BlanketBBFBlock>("blanket");
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_view_matches_filter() {
+        if !crate::filters::blocks::avx2::avx2_available() {
+            eprintln!("skipping avx2_view_matches_filter, AVX2 is unavailable");
+            return;
+        }
+        matches_filter::<crate::filters::blocks::avx2::Avx2BBFBlock>("avx2");
+    }
+
+    #[test]
+    fn truncated_file_fails() {
+        let filter = BBFilter::<BlanketBBFBlock>::with_fpr(K, 10_000, 0.01);
+        let mut bytes = Vec::new();
+        filter.write_to(&mut bytes).unwrap();
+        let path = temp_path("truncated");
+        let results = [bytes.len() - 1, HEADER_LEN, HEADER_LEN - 1, 0].map(|len| {
+            std::fs::write(&path, &bytes[..len]).unwrap();
+            BBFilterView::<BlanketBBFBlock>::open(&path)
+        });
+        std::fs::write(&path, &bytes).unwrap();
+        let whole = BBFilterView::<BlanketBBFBlock>::open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        for result in results {
+            assert!(matches!(result, Err(FilterError::Truncated)));
+        }
+        assert!(whole.is_ok());
+    }
+}
@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate static_assertions;
 
+pub mod counting;
 pub mod filters;
 pub mod parsing;
 pub mod structures;
@@ -91,7 +91,7 @@ impl<R: Read + Send> SeqParser<R, FastqParser<R>> for FastqParser<R> {
             Some(Err(1))
         } else {
             Some(Ok(ReadSeq {
-                name: name,
+                name,
                 sequence: seq,
                 separator: Some(sep),
                 quality: Some(qual),
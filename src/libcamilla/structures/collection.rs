@@ -1,3 +1,23 @@
+// Set of k-mers, either exact or answering membership with false positives
+// Structures are shared between threads, so insertion only needs &self.
 pub trait Collection<T> {
-    fn element(x: T) -> bool;
+    type Error: std::error::Error;
+
+    fn k(&self) -> usize;
+
+    // Add an element, returning whether it was already (possibly) present
+    fn insert(&self, x: T) -> Result<bool, Self::Error>;
+
+    fn contains(&self, x: T) -> bool;
+
+    // Number of distinct elements, exact for exact sets
+    fn len_estimate(&self) -> usize;
+
+    // Probability that contains holds for an absent element, zero for exact sets
+    fn fpr(&self) -> f64;
+
+    // Whether a k-mer and its reverse complement are the same element
+    fn canonical(&self) -> bool {
+        false
+    }
 }
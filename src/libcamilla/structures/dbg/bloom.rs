@@ -0,0 +1,167 @@
+/*
+De Bruijn graph over a k-mer collection, answering edge queries by membership.
+
+Any Collection works: over an exact KmerTable the graph is exact, over a
+Bloom or cuckoo filter false positives add spurious edges, mostly dead ends
+branching off true paths.
+ */
+use std::cell::RefCell;
+
+use crate::structures::{
+    collection::Collection,
+    sequence::{
+        complement::{Complementation, Forward, Identity, Reversal},
+        kmer::{reverse_complement, MAX_K},
+        packed::{PackedSeq, PackedSeqSlice},
+        storage::Storage,
+    },
+};
+
+use super::DeBruijnGraph;
+
+pub struct BloomGraph<S> {
+    set: S,
+}
+
+impl<S> BloomGraph<S>
+where
+    S: for<'a> Collection<PackedSeqSlice<'a, u64, Forward, Identity>>,
+{
+    pub fn new(set: S) -> Self {
+        assert!(
+            (2..=MAX_K).contains(&set.k()),
+            "k must be between 2 and {}",
+            MAX_K
+        );
+        Self { set }
+    }
+
+    #[inline]
+    pub fn set(&self) -> &S {
+        &self.set
+    }
+
+    pub fn into_inner(self) -> S {
+        self.set
+    }
+
+    // Add the k-mers of both strands of a sequence, as edges in both directions.
+    // A canonical collection already merges the strands, so it gets each k-mer once.
+    pub fn insert_seq<T, R, C, E>(&self, seq: &PackedSeq<T, R, C>) -> Result<(), E>
+    where
+        S: for<'a> Collection<PackedSeqSlice<'a, u64, Forward, Identity>, Error = E>,
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        let k = self.set.k();
+        let canonical = self.set.canonical();
+        for kmer in seq.canonical_kmer_iter(k) {
+            with_kmer(kmer, k, |x| self.set.insert(x))?;
+            if !canonical {
+                with_kmer(reverse_complement(kmer, k), k, |x| self.set.insert(x))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    // Reused by every query, so that walking the graph does not allocate
+    static KMER: RefCell<PackedSeq<u64, Forward, Identity>> = RefCell::default();
+}
+
+// Packed storage holds the first base in the most significant bits
+fn with_kmer<O>(
+    kmer: u64,
+    k: usize,
+    f: impl FnOnce(PackedSeqSlice<'_, u64, Forward, Identity>) -> O,
+) -> O {
+    KMER.with(|seq| {
+        let mut seq = seq.borrow_mut();
+        seq.set_word(kmer << (64 - 2 * k), k);
+        f(seq.as_slice())
+    })
+}
+
+impl<S> DeBruijnGraph for BloomGraph<S>
+where
+    S: for<'a> Collection<PackedSeqSlice<'a, u64, Forward, Identity>>,
+{
+    #[inline]
+    fn k(&self) -> usize {
+        self.set.k()
+    }
+
+    fn has_edge(&self, kmer: u64) -> bool {
+        with_kmer(kmer, self.k(), |x| self.set.contains(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        counting::table::KmerTable,
+        filters::{blocks::blanket::BlanketBBFBlock, bloom::BBFilter},
+        structures::sequence::kmer::{kmer_mask, pack_kmer},
+    };
+
+    type Seq = PackedSeq<u64, Forward, Identity>;
+
+    const K: usize = 15;
+
+    // Consecutive k-mers of an inserted sequence are edges on both strands, joined through their nodes
+    fn check_paths<S, E>(graph: &BloomGraph<S>, seq: &Seq)
+    where
+        S: for<'a> Collection<PackedSeqSlice<'a, u64, Forward, Identity>, Error = E>,
+        E: std::fmt::Debug,
+    {
+        graph.insert_seq(seq).unwrap();
+        let kmers: Vec<u64> = (0..=seq.len() - K)
+            .map(|i| pack_kmer(&seq.slice(i, K)))
+            .collect();
+        for &kmer in &kmers {
+            assert!(graph.has_edge(kmer));
+            assert!(graph.has_edge(reverse_complement(kmer, K)));
+        }
+        for pair in kmers.windows(2) {
+            let node = pair[0] & kmer_mask(K - 1);
+            assert!(graph.successors(node).contains(pair[1] & 0b11));
+            assert!(graph
+                .predecessors(pair[1] >> 2)
+                .contains(pair[0] >> (2 * (K - 1))));
+        }
+    }
+
+    #[test]
+    fn same_paths_over_any_collection() {
+        let seq = Seq::random(2000, &mut StdRng::seed_from_u64(42));
+
+        let exact = BloomGraph::new(KmerTable::with_capacity(K, 4096));
+        check_paths(&exact, &seq);
+        let approx = BloomGraph::new(BBFilter::<BlanketBBFBlock>::with_fpr(K, 4096, 1e-3));
+        check_paths(&approx, &seq);
+
+        // Both agree on the k-mers of the sequence, and the exact graph holds nothing else
+        let other = Seq::random(2000, &mut StdRng::seed_from_u64(43));
+        let mut spurious = 0;
+        for i in 0..=other.len() - K {
+            let kmer = pack_kmer(&other.slice(i, K));
+            assert!(!exact.has_edge(kmer));
+            spurious += approx.has_edge(kmer) as usize;
+        }
+        assert!(spurious < 20, "{} spurious edges", spurious);
+    }
+
+    #[test]
+    fn canonical_sets_count_each_kmer_once() {
+        let seq = Seq::random(500, &mut StdRng::seed_from_u64(7));
+        let graph = BloomGraph::new(KmerTable::with_capacity(K, 1024));
+        graph.insert_seq(&seq).unwrap();
+        let total: u64 = graph.set().iter().map(|(_, count)| count as u64).sum();
+        assert_eq!(total, (seq.len() - K + 1) as u64);
+    }
+}
@@ -0,0 +1,533 @@
+/*
+BOSS succinct de Bruijn graph (Bowe, Onodera, Sadakane and Shibuya), as in MEGAHIT.
+
+Edges are sorted by the colexicographic order of their source node, the node
+read backwards, then by their last base. Every edge keeps
+    W   its last base, or $ for the single edge of a node without successors
+    W-  whether an earlier edge with the same base enters the same node
+    L   whether it is the last edge leaving its node
+Nodes sorted the same way match, in order, the edges with a base and no W-
+flag grouped by base, so a step forward or backward is a rank or a select.
+Nodes without predecessors hang off dummy nodes $..$x0..xi, and every node
+but the root $..$ has an incoming edge.
+
+W keeps bases in a BaseRank with $ stored as base 0. Edges that are $ or
+flagged are marked in a bit vector, and their own bases kept in a second
+BaseRank, telling $ apart in a bit vector of their own. Ranks of unflagged
+bases subtract the second BaseRank from the first. With L this is about 5
+bits per edge, plus the dummy edges.
+ */
+use std::{ops::Range, path::Path};
+
+use bitvec::{order::Lsb0, vec::BitVec};
+
+use crate::{
+    counting::{dump::KmerDumpReader, CountingError},
+    structures::sequence::kmer::{kmer_mask, reverse_complement, MAX_K},
+    utils::{base_rank::BaseRank, rank_select::RankSelect},
+};
+
+use super::{Bases, DeBruijnGraph};
+
+// Symbols take three bits, $ is 0 and base x is x + 1
+const SYMBOL_BITS: u32 = 3;
+// Nodes sharing all but their first base lie within this many consecutive nodes
+const GROUP_NODES: usize = 5;
+
+pub struct Boss {
+    k: usize,
+    // Last base of every edge
+    labels: BaseRank,
+    // Edges that are $ or carry a W- flag
+    special: RankSelect,
+    // Bases of the special edges, and which special edges are $
+    special_labels: BaseRank,
+    dollars: RankSelect,
+    last: RankSelect,
+    // Number of nodes whose label ends with a smaller symbol, indexed by symbol
+    c: [usize; 6],
+    // Nodes whose label starts with $, sorted
+    dummies: Vec<usize>,
+    kmer_count: usize,
+}
+
+impl Boss {
+    // Graph whose edges are exactly the given k-mers
+    pub fn new(k: usize, mut kmers: Vec<u64>) -> Self {
+        assert!(
+            (2..=MAX_K).contains(&k),
+            "k must be between 2 and {}",
+            MAX_K
+        );
+        let m = k - 1;
+        kmers.sort_unstable();
+        kmers.dedup();
+
+        let mut sources: Vec<u64> = kmers.iter().map(|e| e >> 2).collect();
+        sources.dedup();
+        let mut targets: Vec<u64> = kmers.iter().map(|e| e & kmer_mask(m)).collect();
+        targets.sort_unstable();
+        targets.dedup();
+
+        let edge_key = |bases: u64, dollars: usize, symbol: u64| {
+            (node_key(bases, dollars, m) << SYMBOL_BITS) | symbol as u128
+        };
+        let mut keys: Vec<u128> = kmers
+            .iter()
+            .map(|e| edge_key(e >> 2, 0, (e & 3) + 1))
+            .collect();
+        for &t in &targets {
+            if sources.binary_search(&t).is_err() {
+                keys.push(edge_key(t, 0, 0));
+            }
+        }
+        for &s in &sources {
+            if targets.binary_search(&s).is_err() {
+                for j in 1..=m {
+                    keys.push(edge_key(s >> (2 * j), j, ((s >> (2 * (j - 1))) & 3) + 1));
+                }
+            }
+        }
+        keys.sort_unstable();
+        keys.dedup();
+
+        let n = keys.len();
+        let mut labels = Vec::with_capacity(n);
+        let mut special = BitVec::<u64, Lsb0>::repeat(false, n);
+        let mut special_labels = Vec::new();
+        let mut dollars = BitVec::<u64, Lsb0>::new();
+        let mut last = BitVec::<u64, Lsb0>::repeat(false, n);
+        let mut nodes_ending = [0; 5];
+        let mut dummies = Vec::new();
+        // Node without its first symbol, of the last unflagged edge with each base
+        let mut entered: [Option<u128>; 4] = [None; 4];
+        let mut node = 0;
+        for (i, &key) in keys.iter().enumerate() {
+            let symbol = (key & 7) as u8;
+            let source = key >> SYMBOL_BITS;
+            let group = source >> SYMBOL_BITS;
+
+            let flagged = symbol > 0 && entered[symbol as usize - 1] == Some(group);
+            if symbol > 0 {
+                entered[symbol as usize - 1] = Some(group);
+            }
+            labels.push(symbol.saturating_sub(1));
+            if symbol == 0 || flagged {
+                special.set(i, true);
+                special_labels.push(symbol.saturating_sub(1));
+                dollars.push(symbol == 0);
+            }
+
+            if i + 1 == n || keys[i + 1] >> SYMBOL_BITS != source {
+                last.set(i, true);
+                nodes_ending[(source >> (SYMBOL_BITS * (m as u32 - 1))) as usize] += 1;
+                if source & 7 == 0 {
+                    dummies.push(node);
+                }
+                node += 1;
+            }
+        }
+
+        let mut c = [0; 6];
+        for s in 0..5 {
+            c[s + 1] = c[s] + nodes_ending[s];
+        }
+
+        Self {
+            k,
+            labels: labels.into_iter().collect(),
+            special: RankSelect::new(special),
+            special_labels: special_labels.into_iter().collect(),
+            dollars: RankSelect::new(dollars),
+            last: RankSelect::new(last),
+            c,
+            dummies,
+            kmer_count: kmers.len(),
+        }
+    }
+
+    // Graph over both strands of canonical k-mers
+    pub fn from_canonical<I: IntoIterator<Item = u64>>(k: usize, kmers: I) -> Self {
+        let kmers = kmers
+            .into_iter()
+            .flat_map(|kmer| [kmer, reverse_complement(kmer, k)])
+            .collect();
+        Self::new(k, kmers)
+    }
+
+    // Graph over both strands of the k-mers of a dump seen at least `min_count` times
+    pub fn from_dump<P: AsRef<Path>>(path: P, min_count: u32) -> Result<Self, CountingError> {
+        let reader = KmerDumpReader::open(path)?;
+        let k = reader.k();
+        let mut kmers = Vec::new();
+        for record in reader {
+            let (kmer, count) = record?;
+            if count >= min_count {
+                kmers.push(kmer);
+            }
+        }
+        Ok(Self::from_canonical(k, kmers))
+    }
+
+    // Number of nodes, dummies included
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        self.last.count_ones()
+    }
+
+    // Number of edges, dummies included
+    #[inline]
+    pub fn edge_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    // Number of k-mers, the edges without dummies
+    #[inline]
+    pub fn kmer_count(&self) -> usize {
+        self.kmer_count
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.labels.memory_bytes()
+            + self.special.memory_bytes()
+            + self.special_labels.memory_bytes()
+            + self.dollars.memory_bytes()
+            + self.last.memory_bytes()
+            + self.dummies.len() * 8
+    }
+
+    #[inline]
+    fn edge_start(&self, v: usize) -> usize {
+        if v == 0 {
+            0
+        } else {
+            self.last.select1(v - 1).unwrap() + 1
+        }
+    }
+
+    // Edges leaving node v
+    #[inline]
+    fn edges(&self, v: usize) -> Range<usize> {
+        self.edge_start(v)..self.edge_start(v + 1)
+    }
+
+    // Base of edge i, None for $
+    #[inline]
+    fn edge_base(&self, i: usize) -> Option<u64> {
+        if self.special.get(i) && self.dollars.get(self.special.rank1(i)) {
+            None
+        } else {
+            Some(self.labels.get(i) as u64)
+        }
+    }
+
+    #[inline]
+    fn is_flagged(&self, i: usize) -> bool {
+        self.special.get(i) && !self.dollars.get(self.special.rank1(i))
+    }
+
+    // Unflagged edges with base x in [0, i), $ edges cancel out as base 0
+    #[inline]
+    fn rank_unflagged(&self, x: u64, i: usize) -> usize {
+        self.labels.rank(x as u8, i) - self.special_labels.rank(x as u8, self.special.rank1(i))
+    }
+
+    // Position of the r-th unflagged edge with base x
+    fn select_unflagged(&self, x: u64, r: usize) -> usize {
+        let (mut lo, mut hi) = (0, self.edge_count());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.rank_unflagged(x, mid + 1) > r {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        lo
+    }
+
+    // Last symbol of the label of node v
+    #[inline]
+    fn last_symbol(&self, v: usize) -> usize {
+        (1..6).find(|&s| v < self.c[s]).unwrap() - 1
+    }
+
+    // Node with the given (k-1)-mer label, narrowing the nodes ending with ever longer prefixes
+    pub fn node_index(&self, label: u64) -> Option<usize> {
+        let m = self.k - 1;
+        let base = |p: usize| (label >> (2 * (m - 1 - p))) & 3;
+        let first = base(0) as usize + 1;
+        let mut range = self.c[first]..self.c[first + 1];
+        for p in 1..m {
+            if range.is_empty() {
+                return None;
+            }
+            let x = base(p);
+            let (e1, e2) = (self.edge_start(range.start), self.edge_start(range.end));
+            let offset = self.c[x as usize + 1];
+            range = offset + self.rank_unflagged(x, e1)..offset + self.rank_unflagged(x, e2);
+        }
+        (range.len() == 1).then_some(range.start)
+    }
+
+    // Label of node v, None for dummy nodes
+    pub fn node_label(&self, mut v: usize) -> Option<u64> {
+        let mut label = 0;
+        for p in 0..self.k - 1 {
+            let s = self.last_symbol(v);
+            if s == 0 {
+                return None;
+            }
+            label |= (s as u64 - 1) << (2 * p);
+            if p + 2 < self.k {
+                v = self.backward_index(v)?;
+            }
+        }
+        Some(label)
+    }
+
+    pub fn forward_index(&self, v: usize, x: u64) -> Option<usize> {
+        self.edges(v)
+            .find(|&i| self.edge_base(i) == Some(x))
+            .map(|i| self.c[x as usize + 1] + self.rank_unflagged(x, i + 1) - 1)
+    }
+
+    // Source of the first edge entering v, None for the root
+    pub fn backward_index(&self, v: usize) -> Option<usize> {
+        let s = self.last_symbol(v);
+        if s == 0 {
+            return None;
+        }
+        let i = self.select_unflagged(s as u64 - 1, v - self.c[s]);
+        Some(self.last.rank1(i))
+    }
+
+    pub fn outdegree_index(&self, v: usize) -> usize {
+        self.edges(v)
+            .filter(|&i| self.edge_base(i).is_some())
+            .count()
+    }
+
+    // Number of edges entering v from non dummy nodes
+    pub fn indegree_index(&self, v: usize) -> usize {
+        let s = self.last_symbol(v);
+        if s == 0 {
+            return 0;
+        }
+        let x = s as u64 - 1;
+        let first = self.select_unflagged(x, v - self.c[s]);
+        let source = self.last.rank1(first);
+        // Dummy nodes only lead to nodes without other predecessors
+        if self.dummies.binary_search(&source).is_ok() {
+            return 0;
+        }
+        // Flagged edges up to the next unflagged one enter the same node
+        let end = self.edge_start((source + GROUP_NODES).min(self.node_count()));
+        1 + (first + 1..end)
+            .filter(|&i| self.edge_base(i) == Some(x))
+            .take_while(|&i| self.is_flagged(i))
+            .count()
+    }
+}
+
+// Colexicographic sort key of a node of m symbols, `dollars` $ followed by m - dollars bases
+fn node_key(bases: u64, dollars: usize, m: usize) -> u128 {
+    let len = m - dollars;
+    (0..m).rev().fold(0, |key, p| {
+        let symbol = if p < dollars {
+            0
+        } else {
+            ((bases >> (2 * (len - 1 - (p - dollars)))) & 3) + 1
+        };
+        (key << SYMBOL_BITS) | symbol as u128
+    })
+}
+
+impl DeBruijnGraph for Boss {
+    #[inline]
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn has_edge(&self, kmer: u64) -> bool {
+        self.node_index(kmer >> 2)
+            .is_some_and(|v| self.edges(v).any(|i| self.edge_base(i) == Some(kmer & 3)))
+    }
+
+    fn successors(&self, node: u64) -> Bases {
+        self.node_index(node).map_or(Bases::default(), |v| {
+            self.edges(v).filter_map(|i| self.edge_base(i)).collect()
+        })
+    }
+
+    fn outdegree(&self, node: u64) -> usize {
+        self.node_index(node).map_or(0, |v| self.outdegree_index(v))
+    }
+
+    fn indegree(&self, node: u64) -> usize {
+        self.node_index(node).map_or(0, |v| self.indegree_index(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        counting::table::KmerTable,
+        structures::{dbg::bloom::BloomGraph, sequence::kmer::canonical},
+    };
+
+    // Boss and the exact graph over a KmerTable, both holding both strands of the k-mers
+    fn graphs(k: usize, kmers: &[u64]) -> (Boss, BloomGraph<KmerTable>) {
+        let table = KmerTable::with_capacity(k, kmers.len().max(1));
+        for &kmer in kmers {
+            table.add(canonical(kmer, k), 1).unwrap();
+        }
+        (
+            Boss::from_canonical(k, kmers.iter().copied()),
+            BloomGraph::new(table),
+        )
+    }
+
+    fn check(boss: &Boss, exact: &BloomGraph<KmerTable>, nodes: impl IntoIterator<Item = u64>) {
+        for node in nodes {
+            for x in 0..4 {
+                assert_eq!(
+                    boss.has_edge((node << 2) | x),
+                    exact.has_edge((node << 2) | x)
+                );
+                assert_eq!(boss.forward(node, x), exact.forward(node, x));
+                assert_eq!(boss.backward(node, x), exact.backward(node, x));
+            }
+            assert_eq!(boss.successors(node), exact.successors(node), "{:x}", node);
+            assert_eq!(
+                boss.predecessors(node),
+                exact.predecessors(node),
+                "{:x}",
+                node
+            );
+            assert_eq!(boss.outdegree(node), exact.outdegree(node), "{:x}", node);
+            assert_eq!(boss.indegree(node), exact.indegree(node), "{:x}", node);
+        }
+    }
+
+    // Prefix and suffix nodes of the k-mers on both strands
+    fn nodes_of(k: usize, kmers: &[u64]) -> Vec<u64> {
+        kmers
+            .iter()
+            .flat_map(|&e| [e, reverse_complement(e, k)])
+            .flat_map(|e| [e >> 2, e & kmer_mask(k - 1)])
+            .collect()
+    }
+
+    // K-mers of a sequence of bases, wrapping around when circular
+    fn seq_kmers(k: usize, bases: &[u64], circular: bool) -> Vec<u64> {
+        let mut bases = bases.to_vec();
+        if circular {
+            bases.extend_from_within(..k - 1);
+        }
+        bases
+            .windows(k)
+            .map(|w| w.iter().fold(0, |acc, x| (acc << 2) | x))
+            .collect()
+    }
+
+    fn random_bases(len: usize, rng: &mut StdRng) -> Vec<u64> {
+        (0..len).map(|_| rng.gen_range(0..4)).collect()
+    }
+
+    #[test]
+    fn small_k_exhaustive() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for k in 2..=4 {
+            for density in [0.05, 0.3, 0.8] {
+                let kmers: Vec<u64> = (0..1u64 << (2 * k))
+                    .filter(|_| rng.gen_bool(density))
+                    .collect();
+                let (boss, exact) = graphs(k, &kmers);
+                check(&boss, &exact, 0..1u64 << (2 * (k - 1)));
+            }
+        }
+    }
+
+    #[test]
+    fn larger_k() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for k in [7, 11, 21, 31] {
+            let mut kmers: Vec<u64> = (0..200).map(|_| rng.gen::<u64>() & kmer_mask(k)).collect();
+            for _ in 0..2 {
+                kmers.extend(seq_kmers(k, &random_bases(200, &mut rng), false));
+            }
+            let (boss, exact) = graphs(k, &kmers);
+            let random_nodes: Vec<u64> = (0..200)
+                .map(|_| rng.gen::<u64>() & kmer_mask(k - 1))
+                .collect();
+            check(&boss, &exact, nodes_of(k, &kmers));
+            check(&boss, &exact, random_nodes);
+        }
+    }
+
+    #[test]
+    fn sources_without_predecessors() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let k = 15;
+        let kmers = seq_kmers(k, &random_bases(200, &mut rng), false);
+        let (boss, exact) = graphs(k, &kmers);
+        let source = kmers[0] >> 2;
+        let sink = reverse_complement(source, k - 1);
+        assert!(boss.predecessors(source).is_empty());
+        assert_eq!(boss.indegree(source), 0);
+        assert_eq!(boss.outdegree(source), 1);
+        assert!(boss.successors(sink).is_empty());
+        assert_eq!(boss.indegree(sink), 1);
+        check(&boss, &exact, nodes_of(k, &kmers));
+    }
+
+    #[test]
+    fn cycles() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let k = 7;
+        let kmers = seq_kmers(k, &random_bases(60, &mut rng), true);
+        let (boss, exact) = graphs(k, &kmers);
+        for node in nodes_of(k, &kmers) {
+            assert!(boss.indegree(node) > 0 && boss.outdegree(node) > 0);
+        }
+        check(&boss, &exact, nodes_of(k, &kmers));
+
+        // A run of As loops on its own node
+        let a = 3;
+        let kmers = seq_kmers(k, &[a; 20], false);
+        let (boss, exact) = graphs(k, &kmers);
+        let node = kmer_mask(k - 1);
+        assert_eq!(boss.forward(node, a), Some(node));
+        assert_eq!(boss.backward(node, a), Some(node));
+        assert_eq!(boss.indegree(node), 1);
+        check(&boss, &exact, [node, 0]);
+    }
+
+    #[test]
+    fn empty() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for k in [2, 5, 31] {
+            let (boss, exact) = graphs(k, &[]);
+            assert_eq!(boss.kmer_count(), 0);
+            check(
+                &boss,
+                &exact,
+                (0..100).map(|_| rng.gen::<u64>() & kmer_mask(k - 1)),
+            );
+        }
+    }
+
+    #[test]
+    fn bits_per_edge() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let k = 31;
+        let kmers = seq_kmers(k, &random_bases(200_000, &mut rng), false);
+        let boss = Boss::from_canonical(k, kmers);
+        let bits = (boss.memory_bytes() * 8) as f64 / boss.edge_count() as f64;
+        assert!((4.0..=5.5).contains(&bits), "{} bits per edge", bits);
+    }
+}
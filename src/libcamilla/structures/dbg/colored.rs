@@ -0,0 +1,450 @@
+/*
+Colored de Bruijn graph, for comparing samples such as strains.
+
+Every sample keeps its own k-mer collection, filled from its read set, and
+the color of an edge is the set of samples whose collection holds it. The
+graph is the union of the samples. Over per-sample filters each sample adds
+its own false positives, so a color may gain a sample with probability about
+the fpr of that sample's filter.
+
+A bubble opens at a node with several successors, whose branches follow
+non-branching paths and meet again at the same node. The color of a branch
+is the set of samples holding all of its edges, and comparing the colors of
+the two branches tells a variant shared by the samples from one telling them
+apart.
+ */
+use crate::structures::{
+    collection::Collection,
+    sequence::{
+        complement::{Complementation, Forward, Identity, Reversal},
+        kmer::{kmer_mask, MAX_K},
+        nucleotide::Nucleotide,
+        packed::{PackedSeq, PackedSeqSlice},
+        storage::Storage,
+    },
+};
+
+use super::{bloom::BloomGraph, Bases, DeBruijnGraph};
+
+pub const MAX_SAMPLES: usize = 64;
+
+// Set of samples, by index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Colors(u64);
+
+impl Colors {
+    // Every sample below `count`
+    pub fn all(count: usize) -> Self {
+        assert!(count <= MAX_SAMPLES, "At most {} samples", MAX_SAMPLES);
+        Colors(if count == MAX_SAMPLES {
+            u64::MAX
+        } else {
+            (1 << count) - 1
+        })
+    }
+
+    #[inline]
+    pub fn insert(&mut self, sample: usize) {
+        debug_assert!(sample < MAX_SAMPLES);
+        self.0 |= 1 << sample;
+    }
+
+    #[inline]
+    pub fn contains(&self, sample: usize) -> bool {
+        sample < MAX_SAMPLES && self.0 >> sample & 1 != 0
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline]
+    pub fn union(&self, other: Colors) -> Colors {
+        Colors(self.0 | other.0)
+    }
+
+    #[inline]
+    pub fn intersection(&self, other: Colors) -> Colors {
+        Colors(self.0 & other.0)
+    }
+
+    #[inline]
+    pub fn is_disjoint(&self, other: Colors) -> bool {
+        self.0 & other.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_SAMPLES).filter(|&s| self.contains(s))
+    }
+}
+
+impl FromIterator<usize> for Colors {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut colors = Colors::default();
+        for s in iter {
+            colors.insert(s);
+        }
+        colors
+    }
+}
+
+// Path from the source of a bubble to its sink
+pub struct Branch {
+    // Bases following the source node, the last k - 1 spelling the sink
+    pub bases: PackedSeq<u64, Forward, Identity>,
+    // Samples holding every edge of the branch
+    pub colors: Colors,
+}
+
+// Derived Clone would require Forward and Identity to be Clone as well
+impl Clone for Branch {
+    fn clone(&self) -> Self {
+        Self {
+            bases: PackedSeq::from_storage(self.bases.storage().to_vec(), self.bases.len()),
+            colors: self.colors,
+        }
+    }
+}
+
+impl Branch {
+    // Number of edges on the branch
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bases.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bases.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BubbleKind {
+    // Both branches are held by the same samples
+    Shared,
+    // No sample holds both branches, the bubble tells the samples apart
+    Disjoint,
+    // Some samples hold both branches and others only one
+    Mixed,
+}
+
+#[derive(Clone)]
+pub struct Bubble {
+    pub source: u64,
+    pub sink: u64,
+    pub branches: [Branch; 2],
+}
+
+impl Bubble {
+    pub fn kind(&self) -> BubbleKind {
+        let [a, b] = &self.branches;
+        if a.colors == b.colors {
+            BubbleKind::Shared
+        } else if a.colors.is_disjoint(b.colors) {
+            BubbleKind::Disjoint
+        } else {
+            BubbleKind::Mixed
+        }
+    }
+}
+
+pub struct ColoredGraph<S> {
+    samples: Vec<BloomGraph<S>>,
+}
+
+impl<S> ColoredGraph<S>
+where
+    S: for<'a> Collection<PackedSeqSlice<'a, u64, Forward, Identity>>,
+{
+    // One collection per sample, in sample order
+    pub fn new(samples: Vec<S>) -> Self {
+        assert!(
+            !samples.is_empty() && samples.len() <= MAX_SAMPLES,
+            "Number of samples must be between 1 and {}",
+            MAX_SAMPLES
+        );
+        let k = samples[0].k();
+        assert!(samples.iter().all(|s| s.k() == k), "Samples must share k");
+        assert!(
+            (2..=MAX_K).contains(&k),
+            "k must be between 2 and {}",
+            MAX_K
+        );
+        Self {
+            samples: samples.into_iter().map(BloomGraph::new).collect(),
+        }
+    }
+
+    // One empty collection per read set from `new_sample`, filled with the reads of both strands
+    pub fn from_read_sets<I, J, T, R, C, E>(
+        read_sets: I,
+        mut new_sample: impl FnMut() -> S,
+    ) -> Result<Self, E>
+    where
+        S: for<'a> Collection<PackedSeqSlice<'a, u64, Forward, Identity>, Error = E>,
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = PackedSeq<T, R, C>>,
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        let mut samples = Vec::new();
+        for reads in read_sets {
+            let sample = BloomGraph::new(new_sample());
+            for read in reads {
+                sample.insert_seq(&read)?;
+            }
+            samples.push(sample.into_inner());
+        }
+        Ok(Self::new(samples))
+    }
+
+    #[inline]
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    #[inline]
+    pub fn sample(&self, sample: usize) -> &BloomGraph<S> {
+        &self.samples[sample]
+    }
+
+    // Add a read of one sample, on both strands
+    pub fn insert_seq<T, R, C, E>(&self, sample: usize, seq: &PackedSeq<T, R, C>) -> Result<(), E>
+    where
+        S: for<'a> Collection<PackedSeqSlice<'a, u64, Forward, Identity>, Error = E>,
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        self.samples[sample].insert_seq(seq)
+    }
+
+    // Samples holding an edge
+    pub fn colors(&self, kmer: u64) -> Colors {
+        (0..self.samples.len())
+            .filter(|&s| self.samples[s].has_edge(kmer))
+            .collect()
+    }
+
+    // Edge held by at least one of the given samples
+    pub fn has_edge_in(&self, kmer: u64, colors: Colors) -> bool {
+        colors
+            .iter()
+            .take_while(|&s| s < self.samples.len())
+            .any(|s| self.samples[s].has_edge(kmer))
+    }
+
+    // Successors along edges held by at least one of the given samples
+    pub fn successors_in(&self, node: u64, colors: Colors) -> Bases {
+        (0..4)
+            .filter(|&x| self.has_edge_in((node << 2) | x, colors))
+            .collect()
+    }
+
+    // Predecessors along edges held by at least one of the given samples
+    pub fn predecessors_in(&self, node: u64, colors: Colors) -> Bases {
+        let shift = 2 * (self.k() - 1);
+        (0..4)
+            .filter(|&x| self.has_edge_in((x << shift) | node, colors))
+            .collect()
+    }
+
+    // Follow the non-branching path starting with the edge node.x for at most max_len edges
+    fn branch(&self, node: u64, x: u64, max_len: usize) -> Option<(u64, Branch)> {
+        let mask = kmer_mask(self.k() - 1);
+        let mut storage: Vec<u64> = Vec::new();
+        let mut len = 0;
+        let mut colors = Colors::all(self.samples.len());
+        let (mut node, mut x) = (node, x);
+        loop {
+            if len == max_len {
+                return None;
+            }
+            let kmer = (node << 2) | x;
+            colors = colors.intersection(self.colors(kmer));
+            let (slot, offset) = u64::addr(len);
+            if slot == storage.len() {
+                storage.push(0);
+            }
+            storage[slot].write(offset, Nucleotide::from(x as u8));
+            len += 1;
+            node = kmer & mask;
+            if self.indegree(node) != 1 {
+                break;
+            }
+            let successors = self.successors(node);
+            if successors.len() != 1 {
+                break;
+            }
+            x = successors.iter().next().unwrap();
+        }
+        let bases = PackedSeq::from_storage(storage, len);
+        Some((node, Branch { bases, colors }))
+    }
+
+    // Bubbles opening at a node, with branches of at most max_len edges
+    pub fn bubbles_from(&self, source: u64, max_len: usize) -> Vec<Bubble> {
+        let branches: Vec<(u64, Branch)> = self
+            .successors(source)
+            .iter()
+            .filter_map(|x| self.branch(source, x, max_len))
+            .collect();
+        let mut bubbles = Vec::new();
+        for (i, (sink, a)) in branches.iter().enumerate() {
+            for (other, b) in &branches[i + 1..] {
+                if sink == other {
+                    bubbles.push(Bubble {
+                        source,
+                        sink: *sink,
+                        branches: [a.clone(), b.clone()],
+                    });
+                }
+            }
+        }
+        bubbles
+    }
+
+    // Bubbles opening at the nodes of a sequence, such as a reference or a read
+    pub fn bubbles_along<T, R, C>(&self, seq: &PackedSeq<T, R, C>, max_len: usize) -> Vec<Bubble>
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        let m = self.k() - 1;
+        let mask = kmer_mask(m);
+        let mut bubbles = Vec::new();
+        let mut node = 0;
+        for (i, x) in seq.iter().enumerate() {
+            node = ((node << 2) | u8::from(x) as u64) & mask;
+            if i + 1 >= m && self.outdegree(node) > 1 {
+                bubbles.extend(self.bubbles_from(node, max_len));
+            }
+        }
+        bubbles
+    }
+}
+
+impl<S> DeBruijnGraph for ColoredGraph<S>
+where
+    S: for<'a> Collection<PackedSeqSlice<'a, u64, Forward, Identity>>,
+{
+    #[inline]
+    fn k(&self) -> usize {
+        self.samples[0].k()
+    }
+
+    fn has_edge(&self, kmer: u64) -> bool {
+        self.samples.iter().any(|s| s.has_edge(kmer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::counting::table::KmerTable;
+
+    const K: usize = 11;
+
+    fn packed(bases: &[u64]) -> PackedSeq<u64, Forward, Identity> {
+        let mut storage = vec![0u64; bases.len().div_ceil(u64::CAPACITY)];
+        for (i, &x) in bases.iter().enumerate() {
+            let (slot, offset) = u64::addr(i);
+            storage[slot].write(offset, Nucleotide::from(x as u8));
+        }
+        PackedSeq::from_storage(storage, bases.len())
+    }
+
+    fn colored(read_sets: &[Vec<&[u64]>]) -> ColoredGraph<KmerTable> {
+        ColoredGraph::from_read_sets(
+            read_sets
+                .iter()
+                .map(|reads| reads.iter().map(|r| packed(r)).collect::<Vec<_>>()),
+            || KmerTable::with_capacity(K, 4096),
+        )
+        .unwrap()
+    }
+
+    // Random reference and a copy with a substitution at `pos`
+    fn snp(rng: &mut StdRng, pos: usize) -> (Vec<u64>, Vec<u64>) {
+        let reference: Vec<u64> = (0..200).map(|_| rng.gen_range(0..4)).collect();
+        let mut alt = reference.clone();
+        alt[pos] = (alt[pos] + rng.gen_range(1..4)) % 4;
+        (reference, alt)
+    }
+
+    fn branch_colors(bubble: &Bubble, first: u64) -> (Colors, Colors) {
+        let [a, b] = &bubble.branches;
+        if u8::from(a.bases.read(0).unwrap()) as u64 == first {
+            (a.colors, b.colors)
+        } else {
+            (b.colors, a.colors)
+        }
+    }
+
+    #[test]
+    fn snp_is_disjoint() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (reference, alt) = snp(&mut rng, 100);
+        let graph = colored(&[vec![&reference], vec![&alt]]);
+        let bubbles = graph.bubbles_along(&packed(&reference), 2 * K);
+        assert_eq!(bubbles.len(), 1);
+        let bubble = &bubbles[0];
+        assert_eq!(bubble.kind(), BubbleKind::Disjoint);
+        assert!(bubble.branches.iter().all(|b| b.len() == K));
+        let (ref_colors, alt_colors) = branch_colors(bubble, reference[100]);
+        assert_eq!(ref_colors, [0].into_iter().collect());
+        assert_eq!(alt_colors, [1].into_iter().collect());
+    }
+
+    #[test]
+    fn shared_variant() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (reference, alt) = snp(&mut rng, 80);
+        let graph = colored(&[vec![&reference, &alt], vec![&alt, &reference]]);
+        let bubbles = graph.bubbles_along(&packed(&reference), 2 * K);
+        assert_eq!(bubbles.len(), 1);
+        assert_eq!(bubbles[0].kind(), BubbleKind::Shared);
+        assert!(bubbles[0]
+            .branches
+            .iter()
+            .all(|b| b.colors == Colors::all(2)));
+
+        // A sample holding only the reference makes it mixed
+        let graph = colored(&[vec![&reference, &alt], vec![&reference]]);
+        let bubbles = graph.bubbles_along(&packed(&reference), 2 * K);
+        assert_eq!(bubbles.len(), 1);
+        assert_eq!(bubbles[0].kind(), BubbleKind::Mixed);
+    }
+
+    #[test]
+    fn max_len_cuts_branches() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let reference: Vec<u64> = (0..200).map(|_| rng.gen_range(0..4)).collect();
+        // Branches of an insertion of five bases have K - 1 and K + 4 edges
+        let mut alt = reference[..100].to_vec();
+        alt.extend((0..5).map(|_| rng.gen_range(0..4)));
+        alt.extend_from_slice(&reference[100..]);
+        let graph = colored(&[vec![&reference], vec![&alt]]);
+        let along = |max_len| graph.bubbles_along(&packed(&reference), max_len);
+
+        assert!(along(K - 2).is_empty());
+        assert!(along(K + 3).is_empty());
+        let bubbles = along(K + 4);
+        assert_eq!(bubbles.len(), 1);
+        let mut lens: Vec<usize> = bubbles[0].branches.iter().map(Branch::len).collect();
+        lens.sort_unstable();
+        assert_eq!(lens, vec![K - 1, K + 4]);
+        assert_eq!(bubbles[0].kind(), BubbleKind::Disjoint);
+    }
+}
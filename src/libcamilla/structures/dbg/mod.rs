@@ -0,0 +1,97 @@
+/*
+De Bruijn graphs over a set of k-mers.
+
+The k-mers of the set are the edges, and nodes are their (k-1)-mer prefixes
+and suffixes, so an edge x0..x(k-1) leaves node x0..x(k-2) and enters node
+x1..x(k-1). K-mers and nodes are packed into a u64 as in kmer.rs, which
+bounds k by MAX_K. Both strands are present only if the set holds both, or
+answers for both as canonical sets do.
+ */
+pub mod bloom;
+pub mod boss;
+pub mod colored;
+
+use super::sequence::kmer::kmer_mask;
+
+// Set of bases, in the Nucleotide encoding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Bases(u8);
+
+impl Bases {
+    #[inline]
+    pub fn insert(&mut self, x: u64) {
+        debug_assert!(x < 4);
+        self.0 |= 1 << x;
+    }
+
+    #[inline]
+    pub fn contains(&self, x: u64) -> bool {
+        self.0 >> x & 1 != 0
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..4).filter(|&x| self.contains(x))
+    }
+}
+
+impl FromIterator<u64> for Bases {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        let mut bases = Bases::default();
+        for x in iter {
+            bases.insert(x);
+        }
+        bases
+    }
+}
+
+// Neighbour queries shared by every de Bruijn graph representation
+pub trait DeBruijnGraph {
+    // Length of the k-mers on edges, nodes are one base shorter
+    fn k(&self) -> usize;
+
+    fn has_edge(&self, kmer: u64) -> bool;
+
+    // Bases x such that node.x is an edge
+    fn successors(&self, node: u64) -> Bases {
+        (0..4).filter(|&x| self.has_edge((node << 2) | x)).collect()
+    }
+
+    // Bases x such that x.node is an edge
+    fn predecessors(&self, node: u64) -> Bases {
+        let shift = 2 * (self.k() - 1);
+        (0..4)
+            .filter(|&x| self.has_edge((x << shift) | node))
+            .collect()
+    }
+
+    fn outdegree(&self, node: u64) -> usize {
+        self.successors(node).len()
+    }
+
+    fn indegree(&self, node: u64) -> usize {
+        self.predecessors(node).len()
+    }
+
+    // Node reached from `node` along the edge node.x
+    fn forward(&self, node: u64, x: u64) -> Option<u64> {
+        self.has_edge((node << 2) | x)
+            .then(|| ((node << 2) | x) & kmer_mask(self.k() - 1))
+    }
+
+    // Node reached backwards from `node` along the edge x.node
+    fn backward(&self, node: u64, x: u64) -> Option<u64> {
+        let shift = 2 * (self.k() - 1);
+        self.has_edge((x << shift) | node)
+            .then(|| ((x << shift) | node) >> 2)
+    }
+}
@@ -0,0 +1,401 @@
+/*
+FM-index over one or more packed sequences, for exact substring search.
+
+The indexed text is the concatenation s1 # s2 # ... sn $, where $ is a unique
+smallest symbol and # sorts between $ and the bases. The bases are copied
+into a single packed sequence, with separator positions kept aside, and the
+suffix array is built on it by SA-IS. The BWT keeps a base in 2 bits per
+row, with rows holding a separator marked in a rank/select bit vector and
+stored as base 0. Ranks of base 0 are corrected for the separator rows.
+
+Locate walks LF from a row until it reaches a sampled one. Suffix array
+values are sampled every `sa_sample` text positions, and every sequence start
+is sampled as well, so walks never cross a separator.
+ */
+use std::ops::Range;
+
+use bitvec::{order::Lsb0, vec::BitVec};
+
+use crate::utils::{base_rank::BaseRank, rank_select::RankSelect};
+
+use super::{
+    sequence::{
+        complement::{Complementation, Forward, Identity, Reversal},
+        packed::{PackedSeq, PackedSeqSlice},
+        storage::Storage,
+    },
+    suffix_array::{sais, SuffixText},
+};
+
+pub const DEFAULT_SA_SAMPLE: usize = 32;
+
+const SENTINEL: usize = 0;
+const SEPARATOR: usize = 1;
+// Bases are shifted past both separators
+const BASE_OFFSET: usize = 2;
+const SIGMA: usize = 6;
+
+// Position of a match, as an offset in one of the indexed sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Occurrence {
+    pub seq: usize,
+    pub pos: usize,
+}
+
+// Indexed text, with the bases of every sequence concatenated in packed storage
+struct FmText {
+    bases: PackedSeq<u64, Forward, Identity>,
+    // Positions of the separators between sequences
+    separators: BitVec<u64, Lsb0>,
+    starts: Vec<usize>,
+}
+
+impl FmText {
+    fn new<T, R, C>(seqs: &[PackedSeq<T, R, C>]) -> Self
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        let len = seqs.iter().map(|s| s.len()).sum::<usize>() + seqs.len().saturating_sub(1);
+        let mut storage = vec![0u64; len.div_ceil(u64::CAPACITY)];
+        let mut separators = BitVec::repeat(false, len);
+        let mut starts = Vec::with_capacity(seqs.len());
+        let mut pos = 0;
+        for (i, seq) in seqs.iter().enumerate() {
+            if i > 0 {
+                separators.set(pos, true);
+                pos += 1;
+            }
+            starts.push(pos);
+            for x in seq.iter() {
+                let (slot, offset) = u64::addr(pos);
+                storage[slot].write(offset, x);
+                pos += 1;
+            }
+        }
+        Self {
+            bases: PackedSeq::from_storage(storage, len),
+            separators,
+            starts,
+        }
+    }
+}
+
+impl SuffixText for FmText {
+    // The sentinel follows the last sequence
+    #[inline]
+    fn len(&self) -> usize {
+        self.bases.len() + 1
+    }
+
+    #[inline]
+    fn symbol(&self, i: usize) -> usize {
+        if i == self.bases.len() {
+            SENTINEL
+        } else if self.separators[i] {
+            SEPARATOR
+        } else {
+            u8::from(self.bases.read(i).unwrap()) as usize + BASE_OFFSET
+        }
+    }
+}
+
+pub struct FmIndex {
+    // BWT bases, with separator rows stored as base 0
+    bwt: BaseRank,
+    // Rows whose BWT symbol is a separator
+    separators: RankSelect,
+    // Rows sorting before the first row starting with each base
+    c: [usize; 4],
+    // Rows with a sampled suffix array value, and the values in row order
+    sampled: RankSelect,
+    samples: Vec<usize>,
+    sa_sample: usize,
+    // Text position of the first base of each sequence
+    starts: Vec<usize>,
+}
+
+impl FmIndex {
+    pub fn new<T, R, C>(seqs: &[PackedSeq<T, R, C>], sa_sample: usize) -> Self
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        assert!(sa_sample > 0, "Suffix array sampling rate must be positive");
+        let text = FmText::new(seqs);
+        let sa = sais(&text, SIGMA);
+
+        let n = text.len();
+        let mut bwt = Vec::with_capacity(n);
+        let mut separators = BitVec::<u64, Lsb0>::repeat(false, n);
+        let mut sampled = BitVec::<u64, Lsb0>::repeat(false, n);
+        let mut samples = Vec::with_capacity(n / sa_sample + seqs.len() + 1);
+        for (row, &pos) in sa.iter().enumerate() {
+            let prev = text.symbol(if pos == 0 { n - 1 } else { pos - 1 });
+            if prev < BASE_OFFSET {
+                separators.set(row, true);
+                bwt.push(0);
+            } else {
+                bwt.push((prev - BASE_OFFSET) as u8);
+            }
+            if prev < BASE_OFFSET || pos % sa_sample == 0 {
+                sampled.set(row, true);
+                samples.push(pos);
+            }
+        }
+        let starts = text.starts;
+
+        let bwt: BaseRank = bwt.into_iter().collect();
+
+        let separators = RankSelect::new(separators);
+        let mut c = [0; 4];
+        let mut before = separators.count_ones();
+        for (x, start) in c.iter_mut().enumerate() {
+            *start = before;
+            before += bwt.count(x as u8);
+            // Separator rows are stored as base 0
+            if x == 0 {
+                before -= separators.count_ones();
+            }
+        }
+
+        Self {
+            bwt,
+            separators,
+            c,
+            sampled: RankSelect::new(sampled),
+            samples,
+            sa_sample,
+            starts,
+        }
+    }
+
+    // Number of rows, the indexed bases plus one separator per sequence
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.separators.len()
+    }
+
+    // Whether no base is indexed
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == self.separators.count_ones()
+    }
+
+    #[inline]
+    pub fn seq_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    #[inline]
+    pub fn sa_sample(&self) -> usize {
+        self.sa_sample
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.bwt.memory_bytes()
+            + self.separators.memory_bytes()
+            + self.sampled.memory_bytes()
+            + self.samples.len() * 8
+            + self.starts.len() * 8
+    }
+
+    // Occurrences of base x in the BWT rows [0, i)
+    #[inline]
+    fn occ(&self, x: usize, i: usize) -> usize {
+        let count = self.bwt.rank(x as u8, i);
+        if x == 0 {
+            count - self.separators.rank1(i)
+        } else {
+            count
+        }
+    }
+
+    #[inline]
+    fn lf(&self, x: usize, i: usize) -> usize {
+        self.c[x] + self.occ(x, i)
+    }
+
+    // Base in the BWT at a row not holding a separator
+    #[inline]
+    fn bwt_base(&self, row: usize) -> usize {
+        self.bwt.get(row) as usize
+    }
+
+    // Rows of the suffixes starting with the pattern, by backward search
+    // The empty pattern has no occurrences, rather than one per row.
+    pub fn range<'a, T, R, C>(&self, pattern: PackedSeqSlice<'a, T, R, C>) -> Range<usize>
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        if pattern.len == 0 {
+            return 0..0;
+        }
+        let (mut sp, mut ep) = (0, self.len());
+        for i in (0..pattern.len).rev() {
+            let x = u8::from(pattern.get(i)) as usize;
+            sp = self.lf(x, sp);
+            ep = self.lf(x, ep);
+            if sp >= ep {
+                return sp..sp;
+            }
+        }
+        sp..ep
+    }
+
+    // Number of occurrences of the pattern on the forward strand of the indexed sequences
+    pub fn count<'a, T, R, C>(&self, pattern: PackedSeqSlice<'a, T, R, C>) -> usize
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        self.range(pattern).len()
+    }
+
+    pub fn contains<'a, T, R, C>(&self, pattern: PackedSeqSlice<'a, T, R, C>) -> bool
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        !self.range(pattern).is_empty()
+    }
+
+    // Text position of the suffix at a row
+    fn suffix(&self, mut row: usize) -> usize {
+        let mut steps = 0;
+        while !self.sampled.get(row) {
+            row = self.lf(self.bwt_base(row), row);
+            steps += 1;
+        }
+        self.samples[self.sampled.rank1(row)] + steps
+    }
+
+    // Every occurrence of the pattern, sorted by sequence and position
+    pub fn locate<'a, T, R, C>(&self, pattern: PackedSeqSlice<'a, T, R, C>) -> Vec<Occurrence>
+    where
+        T: Storage,
+        R: Reversal,
+        C: Complementation,
+    {
+        let mut hits: Vec<Occurrence> = self
+            .range(pattern)
+            .map(|row| {
+                let pos = self.suffix(row);
+                let seq = self.starts.partition_point(|&start| start <= pos) - 1;
+                Occurrence {
+                    seq,
+                    pos: pos - self.starts[seq],
+                }
+            })
+            .collect();
+        hits.sort_unstable();
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::nucleotide::Nucleotide;
+
+    fn packed(s: &[u8]) -> PackedSeq<u64, Forward, Identity> {
+        let mut storage = vec![0u64; s.len().div_ceil(u64::CAPACITY)];
+        for (i, c) in s.iter().enumerate() {
+            let (slot, offset) = u64::addr(i);
+            storage[slot].write(offset, Nucleotide::from_ascii(c));
+        }
+        PackedSeq::from_storage(storage, s.len())
+    }
+
+    fn naive_locate(seqs: &[Vec<u8>], pattern: &[u8]) -> Vec<Occurrence> {
+        let mut hits = Vec::new();
+        if pattern.is_empty() {
+            return hits;
+        }
+        for (seq, s) in seqs.iter().enumerate() {
+            for (pos, window) in s.windows(pattern.len()).enumerate() {
+                if window == pattern {
+                    hits.push(Occurrence { seq, pos });
+                }
+            }
+        }
+        hits
+    }
+
+    fn check(seqs: &[Vec<u8>], patterns: &[Vec<u8>]) {
+        let packed_seqs: Vec<_> = seqs.iter().map(|s| packed(s)).collect();
+        for sa_sample in [1, 3, DEFAULT_SA_SAMPLE] {
+            let index = FmIndex::new(&packed_seqs, sa_sample);
+            assert_eq!(index.seq_count(), seqs.len());
+            for pattern in patterns {
+                let p = packed(pattern);
+                let expected = naive_locate(seqs, pattern);
+                assert_eq!(index.count(p.as_slice()), expected.len());
+                assert_eq!(index.contains(p.as_slice()), !expected.is_empty());
+                assert_eq!(index.locate(p.as_slice()), expected);
+            }
+        }
+    }
+
+    fn random_seq(len: usize, rng: &mut StdRng) -> Vec<u8> {
+        (0..len).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect()
+    }
+
+    #[test]
+    fn matches_naive_search() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let seqs: Vec<Vec<u8>> = [500, 1, 0, 77, 1000]
+            .iter()
+            .map(|&len| random_seq(len, &mut rng))
+            .collect();
+        let mut patterns = Vec::new();
+        for len in 1..10 {
+            for _ in 0..10 {
+                let seq = &seqs[rng.gen_range(0..seqs.len())];
+                if seq.len() >= len {
+                    let start = rng.gen_range(0..=seq.len() - len);
+                    patterns.push(seq[start..start + len].to_vec());
+                }
+                patterns.push(random_seq(len, &mut rng));
+            }
+        }
+        check(&seqs, &patterns);
+    }
+
+    #[test]
+    fn repeats() {
+        let seqs = vec![b"AAAAAAAA".to_vec(), b"AAAA".to_vec(), b"ACACACAC".to_vec()];
+        let patterns: Vec<Vec<u8>> = ["A", "AA", "AAAAA", "AAAAAAAAA", "AC", "CA", "CAC", "G"]
+            .iter()
+            .map(|p| p.as_bytes().to_vec())
+            .collect();
+        check(&seqs, &patterns);
+    }
+
+    #[test]
+    fn no_match_across_separators() {
+        let seqs = vec![b"ACGT".to_vec(), b"TTGA".to_vec(), b"CCCC".to_vec()];
+        let patterns: Vec<Vec<u8>> = ["GTTT", "ACGTTTGA", "TC", "GAC", "ACGT", "CCCC"]
+            .iter()
+            .map(|p| p.as_bytes().to_vec())
+            .collect();
+        check(&seqs, &patterns);
+    }
+
+    #[test]
+    fn empty_pattern() {
+        let index = FmIndex::new(&[packed(b"ACGT"), packed(b"GG")], 4);
+        let empty = packed(b"");
+        assert_eq!(index.count(empty.as_slice()), 0);
+        assert!(!index.contains(empty.as_slice()));
+        assert!(index.locate(empty.as_slice()).is_empty());
+    }
+}
@@ -1,3 +1,6 @@
 pub mod collection;
+pub mod dbg;
+pub mod fm_index;
 pub mod bigraph;
 pub mod sequence;
+pub mod suffix_array;
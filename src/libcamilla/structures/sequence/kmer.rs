@@ -0,0 +1,108 @@
+/*
+K-mers packed into a u64, two bits per base in the Nucleotide encoding,
+first base in the most significant position. Supports k up to 32.
+ */
+use super::{
+    complement::{Complementation, Reversal},
+    nucleotide::Nucleotide,
+    packed::{PackedSeq, PackedSeqSlice},
+    storage::Storage,
+};
+
+pub const MAX_K: usize = 32;
+
+#[inline]
+pub fn kmer_mask(k: usize) -> u64 {
+    if k >= MAX_K {
+        u64::MAX
+    } else {
+        (1 << (2 * k)) - 1
+    }
+}
+
+// In the Nucleotide encoding the complement of x is 3 - x
+#[inline]
+pub fn complement_bits(x: u64) -> u64 {
+    3 - x
+}
+
+pub fn reverse_complement(kmer: u64, k: usize) -> u64 {
+    (0..k).fold(0, |acc, i| {
+        (acc << 2) | complement_bits((kmer >> (2 * i)) & 0b11)
+    })
+}
+
+// Smaller of a k-mer and its reverse complement
+#[inline]
+pub fn canonical(kmer: u64, k: usize) -> u64 {
+    kmer.min(reverse_complement(kmer, k))
+}
+
+pub fn pack_kmer<'a, T, R, C>(kmer: &PackedSeqSlice<'a, T, R, C>) -> u64
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    debug_assert!(kmer.len <= MAX_K);
+    (0..kmer.len).fold(0, |acc, i| (acc << 2) | kmer.get(i) as u64)
+}
+
+pub fn unpack_kmer(kmer: u64, k: usize) -> impl Iterator<Item = Nucleotide> {
+    (0..k)
+        .rev()
+        .map(move |i| Nucleotide::from(kmer >> (2 * i) & 0b11))
+}
+
+// Iterator over the canonical form of every k-mer in a sequence slice
+#[derive(Debug)]
+pub struct CanonicalKmerIter<'a, T: Storage, R: Reversal, C: Complementation> {
+    data: PackedSeqSlice<'a, T, R, C>,
+    pos: usize,
+    k: usize,
+    forward: u64,
+    reverse: u64,
+    mask: u64,
+}
+
+impl<'a, T: Storage, R: Reversal, C: Complementation> CanonicalKmerIter<'a, T, R, C> {
+    pub fn new(data: PackedSeqSlice<'a, T, R, C>, k: usize) -> Self {
+        assert!(k > 0 && k <= MAX_K, "k must be between 1 and {}", MAX_K);
+        Self {
+            data,
+            pos: 0,
+            k,
+            forward: 0,
+            reverse: 0,
+            mask: kmer_mask(k),
+        }
+    }
+}
+
+impl<'a, T: Storage, R: Reversal, C: Complementation> Iterator for CanonicalKmerIter<'a, T, R, C> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.data.len {
+            let x = self.data.get(self.pos) as u64;
+            self.forward = ((self.forward << 2) | x) & self.mask;
+            self.reverse = (self.reverse >> 2) | (complement_bits(x) << (2 * (self.k - 1)));
+            self.pos += 1;
+            if self.pos >= self.k {
+                return Some(self.forward.min(self.reverse));
+            }
+        }
+        None
+    }
+}
+
+impl<T, R, C> PackedSeq<T, R, C>
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    pub fn canonical_kmer_iter(&self, k: usize) -> CanonicalKmerIter<'_, T, R, C> {
+        CanonicalKmerIter::new(self.as_slice(), k)
+    }
+}
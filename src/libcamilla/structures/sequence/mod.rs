@@ -1,4 +1,5 @@
 pub mod complement;
+pub mod kmer;
 pub mod nucleotide;
 pub mod packed;
 pub mod read;
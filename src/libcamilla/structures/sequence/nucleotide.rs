@@ -1,5 +1,6 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub enum Nucleotide {
+    #[default]
     T = 0,
     A = 3,
     G = 1,
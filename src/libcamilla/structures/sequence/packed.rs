@@ -1,5 +1,6 @@
 use super::{nucleotide::Nucleotide, read::ReadSeq, storage::Storage};
 use crate::structures::sequence::complement::{Complementation, Forward, Reversal, Reverse};
+use rand::Rng;
 use std::{fmt::Display, marker::PhantomData};
 
 #[derive(Debug, Clone)]
@@ -30,6 +31,11 @@ where
         self.len
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     pub fn with_capacity(n: usize) -> Self {
         Self {
             // Better than calculating directly,
@@ -56,6 +62,44 @@ where
         res
     }
 
+    // Uniformly random sequence, its k-mers are absent from any given set with high probability
+    pub fn random<G: Rng>(len: usize, rng: &mut G) -> Self {
+        let mut storage: Vec<T> = Vec::with_capacity(len.div_ceil(T::CAPACITY));
+        let mut left = len;
+        while left > 0 {
+            let chunk = left.min(T::CAPACITY);
+            let mut word = T::default();
+            word.write_chunk((0..chunk).map(|_| Nucleotide::from(rng.gen::<u8>() & 0b11)));
+            storage.push(word);
+            left -= chunk;
+        }
+        Self::from_storage(storage, len)
+    }
+
+    // Wrap already packed storage holding len bases
+    pub fn from_storage(storage: Vec<T>, len: usize) -> Self {
+        debug_assert!(len <= storage.len() * T::CAPACITY);
+        Self {
+            storage,
+            len,
+            _r: PhantomData,
+            _c: PhantomData,
+        }
+    }
+
+    // Replace the contents with one packed word holding len bases, reusing the allocation
+    pub fn set_word(&mut self, word: T, len: usize) {
+        debug_assert!(len <= T::CAPACITY);
+        self.storage.clear();
+        self.storage.push(word);
+        self.len = len;
+    }
+
+    #[inline]
+    pub fn storage(&self) -> &[T] {
+        &self.storage
+    }
+
     pub fn read(&self, n: usize) -> Option<Nucleotide> {
         if n < self.len {
             let (slot, pos) = T::addr(R::reindex(self.len, n));
@@ -110,7 +154,7 @@ where
     pub fn reverse_complement(self) -> PackedSeq<T, R::Inverse, C::Inverse> {
         PackedSeq::<T, R::Inverse, C::Inverse> {
             // Noop
-            storage: self.storage.into_iter().map(|x| x.into()).collect(),
+            storage: self.storage.into_iter().collect(),
             len: self.len,
             _r: PhantomData,
             _c: PhantomData,
@@ -136,6 +180,17 @@ where
     }
 }
 
+impl<T, R, C> Default for PackedSeq<T, R, C>
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PackedSeqIter<'a, T, R, C>
 where
@@ -192,7 +247,7 @@ where
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug)]
 pub struct PackedSeqSlice<'a, T, R, C>
 where
     T: Storage,
@@ -204,6 +259,26 @@ where
     pub len: usize,
 }
 
+// Derived impls would require T, R and C to be Copy as well
+impl<'a, T, R, C> Clone for PackedSeqSlice<'a, T, R, C>
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, R, C> Copy for PackedSeqSlice<'a, T, R, C>
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+}
+
 impl<'a, T, R, C> PackedSeqSlice<'a, T, R, C>
 where
     T: Storage,
@@ -291,3 +366,56 @@ macro_rules! repack_by_extension {
 }
 
 repack_by_extension!(u8, u16, u32, u64, u128, usize, Nucleotide);
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::complement::Identity;
+
+    fn check_random<T: Storage>() {
+        for len in [
+            0,
+            1,
+            T::CAPACITY - 1,
+            T::CAPACITY,
+            3 * T::CAPACITY + 1,
+            40_000,
+        ] {
+            let seq = PackedSeq::<T, Forward, Identity>::random(len, &mut StdRng::seed_from_u64(1));
+            assert_eq!(seq.len(), len);
+            assert_eq!(seq.storage().len(), len.div_ceil(T::CAPACITY));
+            assert_eq!(seq.iter().count(), len);
+            assert!(seq.read(len).is_none());
+        }
+    }
+
+    #[test]
+    fn random_fills_every_slot() {
+        check_random::<u8>();
+        check_random::<u64>();
+        check_random::<u128>();
+    }
+
+    #[test]
+    fn random_is_uniform_and_seeded() {
+        let seq =
+            PackedSeq::<u64, Forward, Identity>::random(40_000, &mut StdRng::seed_from_u64(2));
+        let mut counts = [0usize; 4];
+        for x in seq.iter() {
+            counts[x as usize] += 1;
+        }
+        // 10 000 expected per base, with a standard deviation near 90
+        for count in counts {
+            assert!(count.abs_diff(10_000) < 500, "{:?}", counts);
+        }
+
+        let again =
+            PackedSeq::<u64, Forward, Identity>::random(40_000, &mut StdRng::seed_from_u64(2));
+        assert_eq!(seq.storage(), again.storage());
+        let other =
+            PackedSeq::<u64, Forward, Identity>::random(40_000, &mut StdRng::seed_from_u64(3));
+        assert_ne!(seq.storage(), other.storage());
+    }
+}
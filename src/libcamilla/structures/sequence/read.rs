@@ -14,11 +14,16 @@ pub struct ReadSeq {
 
 impl ReadSeq {
     pub fn pack<T: Storage>(&self) -> PackedSeq<T, Forward, Identity> {
-        PackedSeq::<T, Forward, Identity>::from_read(&self)
+        PackedSeq::<T, Forward, Identity>::from_read(self)
     }
 
     #[inline]
     pub fn len(&self) -> usize {
         self.sequence.len()
     }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.sequence.is_empty()
+    }
 }
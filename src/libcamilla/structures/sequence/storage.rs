@@ -60,12 +60,6 @@ macro_rules! storage_impl {
 
 storage_impl!(u8, u16, u32, u64, u128, usize);
 
-impl Default for Nucleotide {
-    fn default() -> Self {
-        Nucleotide::T
-    }
-}
-
 // For PackedSeq's with one byte per base
 impl Storage for Nucleotide {
     const WIDTH: usize = 2;
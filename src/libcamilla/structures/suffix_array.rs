@@ -0,0 +1,385 @@
+/*
+Suffix arrays by SA-IS, and LCP arrays by Kasai's algorithm.
+
+Both run in linear time on any SuffixText, a text over symbols [0, sigma)
+whose last symbol is a unique smallest sentinel. PackedText reads bases
+straight from packed storage as symbols A = 1, C = 2, G = 3 and T = 4, after
+which a virtual sentinel 0 follows, so no unpacked copy of the sequence is
+made. Suffixes of packed sequences therefore sort alphabetically, although
+the 2 bit codes order the bases T < G < C < A.
+
+SA-IS classifies suffixes as S-type (smaller than the next suffix) or L-type,
+sorts the LMS substrings that start at the leftmost S of each S run by
+inducing, names them, and recurses on the names when they are not unique.
+The sorted LMS suffixes then induce the order of every other suffix.
+ */
+use super::sequence::{
+    complement::{Complementation, Reversal},
+    packed::PackedSeqSlice,
+    storage::Storage,
+};
+
+pub trait SuffixText {
+    fn len(&self) -> usize;
+    fn symbol(&self, i: usize) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SuffixText for [usize] {
+    #[inline]
+    fn len(&self) -> usize {
+        <[usize]>::len(self)
+    }
+
+    #[inline]
+    fn symbol(&self, i: usize) -> usize {
+        self[i]
+    }
+}
+
+// Bases and the sentinel
+pub const PACKED_SIGMA: usize = 5;
+
+// Packed sequence followed by a virtual sentinel
+pub struct PackedText<'a, T: Storage, R: Reversal, C: Complementation> {
+    seq: PackedSeqSlice<'a, T, R, C>,
+}
+
+impl<'a, T: Storage, R: Reversal, C: Complementation> PackedText<'a, T, R, C> {
+    pub fn new(seq: PackedSeqSlice<'a, T, R, C>) -> Self {
+        Self { seq }
+    }
+}
+
+impl<'a, T: Storage, R: Reversal, C: Complementation> SuffixText for PackedText<'a, T, R, C> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.seq.len + 1
+    }
+
+    #[inline]
+    fn symbol(&self, i: usize) -> usize {
+        if i == self.seq.len {
+            0
+        } else {
+            // Codes run T, G, C, A from 0 to 3
+            PACKED_SIGMA - 1 - u8::from(self.seq.get(i)) as usize
+        }
+    }
+}
+
+// Suffix array of a sequence in alphabetical order A < C < G < T, the sentinel suffix left out
+pub fn suffix_array<'a, T, R, C>(seq: PackedSeqSlice<'a, T, R, C>) -> Vec<usize>
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    let mut sa = sais(&PackedText::new(seq), PACKED_SIGMA);
+    // The sentinel suffix sorts first
+    sa.remove(0);
+    sa
+}
+
+// LCP of each suffix in `sa` with the previous one, zero for the first
+pub fn lcp_array<'a, T, R, C>(seq: PackedSeqSlice<'a, T, R, C>, sa: &[usize]) -> Vec<usize>
+where
+    T: Storage,
+    R: Reversal,
+    C: Complementation,
+{
+    assert_eq!(
+        sa.len(),
+        seq.len,
+        "Suffix array does not match the sequence"
+    );
+    let text = PackedText::new(seq);
+    // Suffixes of the sequence compare as in the text, only the sentinel row is missing
+    let mut full = Vec::with_capacity(sa.len() + 1);
+    full.push(seq.len);
+    full.extend_from_slice(sa);
+    let mut lcp = kasai(&text, &full);
+    // Nothing is shared with the sentinel, so the first suffix keeps zero
+    lcp.remove(0);
+    lcp
+}
+
+const EMPTY: usize = usize::MAX;
+
+// Suffix array of a text over [0, sigma) ending in a unique smallest symbol
+pub fn sais<S: SuffixText + ?Sized>(text: &S, sigma: usize) -> Vec<usize> {
+    let n = text.len();
+    assert!(n > 0, "Text must end in a sentinel");
+    if n == 1 {
+        return vec![0];
+    }
+
+    let mut stype = vec![false; n];
+    stype[n - 1] = true;
+    for i in (0..n - 1).rev() {
+        let (a, b) = (text.symbol(i), text.symbol(i + 1));
+        stype[i] = a < b || (a == b && stype[i + 1]);
+    }
+    let is_lms = |i: usize| i > 0 && stype[i] && !stype[i - 1];
+
+    let mut buckets = vec![0; sigma];
+    for i in 0..n {
+        buckets[text.symbol(i)] += 1;
+    }
+
+    // Sort LMS substrings by inducing from LMS suffixes in text order
+    let mut sa = vec![EMPTY; n];
+    let mut tails = bucket_tails(&buckets);
+    for i in (1..n).rev().filter(|&i| is_lms(i)) {
+        let x = text.symbol(i);
+        tails[x] -= 1;
+        sa[tails[x]] = i;
+    }
+    induce(text, &stype, &buckets, &mut sa);
+
+    let mut m = 0;
+    for i in 0..n {
+        if is_lms(sa[i]) {
+            sa[m] = sa[i];
+            m += 1;
+        }
+    }
+    sa[m..].fill(EMPTY);
+
+    // Name LMS substrings, LMS positions are at least two apart so pos / 2 is free
+    let mut names = 0;
+    let mut prev = EMPTY;
+    for i in 0..m {
+        let pos = sa[i];
+        if prev == EMPTY || !lms_equal(text, &stype, prev, pos) {
+            names += 1;
+        }
+        prev = pos;
+        sa[m + pos / 2] = names - 1;
+    }
+    let mut j = n;
+    for i in (m..n).rev() {
+        if sa[i] != EMPTY {
+            j -= 1;
+            sa[j] = sa[i];
+        }
+    }
+
+    // Sort LMS suffixes, recursing while their substrings are not unique
+    let reduced = &sa[n - m..];
+    let reduced_sa = if names < m {
+        sais(reduced, names)
+    } else {
+        let mut reduced_sa = vec![0; m];
+        for (i, &name) in reduced.iter().enumerate() {
+            reduced_sa[name] = i;
+        }
+        reduced_sa
+    };
+
+    let lms: Vec<usize> = (1..n).filter(|&i| is_lms(i)).collect();
+    sa.fill(EMPTY);
+    let mut tails = bucket_tails(&buckets);
+    for &r in reduced_sa.iter().rev() {
+        let pos = lms[r];
+        let x = text.symbol(pos);
+        tails[x] -= 1;
+        sa[tails[x]] = pos;
+    }
+    induce(text, &stype, &buckets, &mut sa);
+    sa
+}
+
+// LCP of each suffix in the suffix array of a text with the previous one, zero for the first
+pub fn kasai<S: SuffixText + ?Sized>(text: &S, sa: &[usize]) -> Vec<usize> {
+    let n = text.len();
+    assert_eq!(sa.len(), n, "Suffix array does not match the text");
+    let mut rank = vec![0; n];
+    for (i, &pos) in sa.iter().enumerate() {
+        rank[pos] = i;
+    }
+
+    // The LCP of the suffix at i + 1 is at least that of the suffix at i, minus one
+    let mut lcp = vec![0; n];
+    let mut h = 0;
+    for i in 0..n {
+        if rank[i] == 0 {
+            h = 0;
+            continue;
+        }
+        let j = sa[rank[i] - 1];
+        // The sentinel is unique, so a match always ends before the text does
+        while text.symbol(i + h) == text.symbol(j + h) {
+            h += 1;
+        }
+        lcp[rank[i]] = h;
+        h = h.saturating_sub(1);
+    }
+    lcp
+}
+
+fn bucket_heads(buckets: &[usize]) -> Vec<usize> {
+    let mut sum = 0;
+    buckets
+        .iter()
+        .map(|&b| {
+            sum += b;
+            sum - b
+        })
+        .collect()
+}
+
+fn bucket_tails(buckets: &[usize]) -> Vec<usize> {
+    let mut sum = 0;
+    buckets
+        .iter()
+        .map(|&b| {
+            sum += b;
+            sum
+        })
+        .collect()
+}
+
+// Induce L-type suffixes left to right, then S-type suffixes right to left
+fn induce<S: SuffixText + ?Sized>(text: &S, stype: &[bool], buckets: &[usize], sa: &mut [usize]) {
+    let mut heads = bucket_heads(buckets);
+    for i in 0..sa.len() {
+        let j = sa[i];
+        if j != EMPTY && j > 0 && !stype[j - 1] {
+            let x = text.symbol(j - 1);
+            sa[heads[x]] = j - 1;
+            heads[x] += 1;
+        }
+    }
+    let mut tails = bucket_tails(buckets);
+    for i in (0..sa.len()).rev() {
+        let j = sa[i];
+        if j != EMPTY && j > 0 && stype[j - 1] {
+            let x = text.symbol(j - 1);
+            tails[x] -= 1;
+            sa[tails[x]] = j - 1;
+        }
+    }
+}
+
+// Whether the LMS substrings starting at a and b are equal
+fn lms_equal<S: SuffixText + ?Sized>(text: &S, stype: &[bool], a: usize, b: usize) -> bool {
+    let n = text.len();
+    // The sentinel is unique
+    if a == n - 1 || b == n - 1 {
+        return a == b;
+    }
+    let is_lms = |i: usize| stype[i] && !stype[i - 1];
+    let mut i = 0;
+    loop {
+        let (x, y) = (a + i, b + i);
+        if text.symbol(x) != text.symbol(y) || stype[x] != stype[y] {
+            return false;
+        }
+        if i > 0 && (is_lms(x) || is_lms(y)) {
+            return is_lms(x) && is_lms(y);
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::structures::sequence::{
+        complement::{Forward, Identity},
+        nucleotide::Nucleotide,
+        packed::PackedSeq,
+    };
+
+    fn packed(s: &[u8]) -> PackedSeq<u64, Forward, Identity> {
+        let mut storage = vec![0u64; s.len().div_ceil(u64::CAPACITY)];
+        for (i, c) in s.iter().enumerate() {
+            let (slot, offset) = u64::addr(i);
+            storage[slot].write(offset, Nucleotide::from_ascii(c));
+        }
+        PackedSeq::from_storage(storage, s.len())
+    }
+
+    fn naive_sa<X: Ord>(s: &[X]) -> Vec<usize> {
+        let mut sa: Vec<usize> = (0..s.len()).collect();
+        sa.sort_by(|&a, &b| s[a..].cmp(&s[b..]));
+        sa
+    }
+
+    fn naive_lcp<X: Eq>(s: &[X], sa: &[usize]) -> Vec<usize> {
+        let mut lcp = vec![0; sa.len()];
+        for i in 1..sa.len() {
+            lcp[i] = s[sa[i - 1]..]
+                .iter()
+                .zip(&s[sa[i]..])
+                .take_while(|(a, b)| a == b)
+                .count();
+        }
+        lcp
+    }
+
+    fn check(s: &[u8]) {
+        let seq = packed(s);
+        let sa = suffix_array(seq.as_slice());
+        assert_eq!(sa, naive_sa(s), "{}", String::from_utf8_lossy(s));
+        assert_eq!(lcp_array(seq.as_slice(), &sa), naive_lcp(s, &sa));
+    }
+
+    #[test]
+    fn alphabetical_order() {
+        check(b"TGCA");
+        assert_eq!(suffix_array(packed(b"TGCA").as_slice()), vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn short_and_repetitive() {
+        for s in [
+            &b""[..],
+            b"A",
+            b"T",
+            b"AC",
+            b"CA",
+            b"AA",
+            b"AAAA",
+            b"TTTTTTTTT",
+            b"ACACACACA",
+            b"GATTACA",
+            b"AAAAACAAAAAC",
+        ] {
+            check(s);
+        }
+    }
+
+    #[test]
+    fn random_sequences() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for len in [3, 10, 100, 1000, 5000] {
+            for alphabet in [&b"AC"[..], b"ACGT"] {
+                let s: Vec<u8> = (0..len)
+                    .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                    .collect();
+                check(&s);
+            }
+        }
+    }
+
+    #[test]
+    fn integer_alphabets() {
+        let mut rng = StdRng::seed_from_u64(9);
+        for sigma in [2, 3, 50] {
+            for len in [1, 2, 7, 300] {
+                let mut text: Vec<usize> = (0..len).map(|_| rng.gen_range(1..sigma)).collect();
+                text.push(0);
+                let sa = sais(&text[..], sigma);
+                assert_eq!(sa, naive_sa(&text));
+                assert_eq!(kasai(&text[..], &sa), naive_lcp(&text, &sa));
+            }
+        }
+    }
+}
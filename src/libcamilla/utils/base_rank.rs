@@ -0,0 +1,122 @@
+/*
+Rank over a sequence of 2 bit symbols, such as bases in the Nucleotide encoding.
+
+Symbols are packed 32 per word, the first in the least significant bits, and
+the occurrences of each symbol are counted before every SAMPLE-th position,
+half a bit per symbol. A rank adds the popcounts of the matching symbols in
+at most SAMPLE / 32 words to the preceding sample.
+ */
+const SYMBOLS_PER_WORD: usize = 32;
+const SAMPLE: usize = 512;
+const WORDS_PER_SAMPLE: usize = SAMPLE / SYMBOLS_PER_WORD;
+const LOW_BITS: u64 = 0x5555_5555_5555_5555;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseRank {
+    words: Vec<u64>,
+    // Occurrences of each symbol before every SAMPLE-th position
+    samples: Vec<[u64; 4]>,
+    len: usize,
+}
+
+impl FromIterator<u8> for BaseRank {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        let mut words = Vec::new();
+        let mut len = 0;
+        for x in iter {
+            debug_assert!(x < 4);
+            if len % SYMBOLS_PER_WORD == 0 {
+                words.push(0);
+            }
+            words[len / SYMBOLS_PER_WORD] |= (x as u64) << (2 * (len % SYMBOLS_PER_WORD));
+            len += 1;
+        }
+        Self::from_words(words, len)
+    }
+}
+
+impl BaseRank {
+    // Index `len` symbols packed in words, 32 per word with the first in the least significant bits
+    pub fn from_words(mut words: Vec<u64>, len: usize) -> Self {
+        assert!(
+            words.len() >= len.div_ceil(SYMBOLS_PER_WORD),
+            "Too few words for the symbols"
+        );
+        words.truncate(len.div_ceil(SYMBOLS_PER_WORD));
+        // Padding is cleared so that equal indexes compare equal
+        if !len.is_multiple_of(SYMBOLS_PER_WORD) {
+            *words.last_mut().unwrap() &= (1 << (2 * (len % SYMBOLS_PER_WORD))) - 1;
+        }
+
+        let mut samples = Vec::with_capacity(len / SAMPLE + 1);
+        let mut counts = [0u64; 4];
+        for block in 0..=len / SAMPLE {
+            samples.push(counts);
+            let start = block * WORDS_PER_SAMPLE;
+            for (w, word) in words.iter().enumerate().skip(start).take(WORDS_PER_SAMPLE) {
+                // Padding reads as symbol 0, only count up to len
+                let symbols = SYMBOLS_PER_WORD.min(len - w * SYMBOLS_PER_WORD);
+                for (x, count) in counts.iter_mut().enumerate() {
+                    *count += count_in_word(*word, x as u64, symbols);
+                }
+            }
+        }
+        Self {
+            words,
+            samples,
+            len,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn get(&self, i: usize) -> u8 {
+        debug_assert!(i < self.len);
+        (self.words[i / SYMBOLS_PER_WORD] >> (2 * (i % SYMBOLS_PER_WORD)) & 0b11) as u8
+    }
+
+    // Occurrences of symbol x in [0, i)
+    #[inline]
+    pub fn rank(&self, x: u8, i: usize) -> usize {
+        debug_assert!(x < 4 && i <= self.len);
+        let block = i / SAMPLE;
+        let mut count = self.samples[block][x as usize];
+        let word = i / SYMBOLS_PER_WORD;
+        for w in &self.words[block * WORDS_PER_SAMPLE..word] {
+            count += count_in_word(*w, x as u64, SYMBOLS_PER_WORD);
+        }
+        if !i.is_multiple_of(SYMBOLS_PER_WORD) {
+            count += count_in_word(self.words[word], x as u64, i % SYMBOLS_PER_WORD);
+        }
+        count as usize
+    }
+
+    #[inline]
+    pub fn count(&self, x: u8) -> usize {
+        self.rank(x, self.len)
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.words.len() * 8 + self.samples.len() * 32
+    }
+}
+
+// Occurrences of the 2 bit value x among the first `symbols` symbols of a word
+#[inline]
+fn count_in_word(word: u64, x: u64, symbols: usize) -> u64 {
+    let eq = !(word ^ (x * LOW_BITS));
+    let mut matches = eq & (eq >> 1) & LOW_BITS;
+    if symbols < SYMBOLS_PER_WORD {
+        matches &= (1 << (2 * symbols)) - 1;
+    }
+    matches.count_ones() as u64
+}
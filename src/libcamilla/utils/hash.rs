@@ -0,0 +1,16 @@
+// Finalizer from MurmurHash3.
+// A bijection on u64 with good avalanche, so distinct salts give distinct outputs.
+#[inline]
+pub fn mix64(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51afd7ed558ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ceb9fe1a85ec53);
+    x ^= x >> 33;
+    x
+}
+
+// Fresh seed for an independent replicate of a seeded structure
+pub fn random_seed() -> u64 {
+    rand::random()
+}
@@ -1 +1,4 @@
 pub mod addr;
+pub mod base_rank;
+pub mod hash;
+pub mod rank_select;
@@ -0,0 +1,453 @@
+/*
+Succinct bit vector with rank and select.
+
+Rank follows rank9: bits are grouped in blocks of eight 64 bit words, and
+every block stores the number of ones before it, plus seven 9 bit counts of
+the ones before each of its words packed in a second u64. A rank is two
+lookups and a popcount, at 25% overhead.
+Select follows select9: every SELECT_SAMPLE-th one (or zero) is sampled. When
+the run of SELECT_SAMPLE ones starting at a sample spans more than SPARSE_SPAN
+bits, the positions of all its ones are stored outright, at most 1/8 of the
+bits the run covers. Otherwise the run covers at most SPARSE_SPAN / 512 blocks,
+which are binary searched in at most 12 steps, before narrowing down to a word
+with the packed counts and to a bit with popcounts.
+
+Persisted format, all integers little endian:
+    [0, 8)    magic "CAMRSB\0\0"
+    [8, 12)   format version
+    [12, 20)  number of bits
+followed by the bits in u64 words, bit i being bit i % 64 of word i / 64.
+The index is rebuilt on load.
+ */
+use std::io::{self, Read, Write};
+
+use bitvec::{order::Lsb0, slice::BitSlice, vec::BitVec};
+
+pub const MAGIC: [u8; 8] = *b"CAMRSB\0\0";
+pub const FORMAT_VERSION: u32 = 1;
+pub const HEADER_LEN: usize = 20;
+
+const WORDS_PER_BLOCK: usize = 8;
+const BLOCK_BITS: usize = 64 * WORDS_PER_BLOCK;
+const SELECT_SAMPLE: usize = 4096;
+const SPARSE_SPAN: usize = SELECT_SAMPLE * 64 * 8;
+// Words read at a time when loading, so a corrupt length cannot reserve unbounded memory
+const READ_CHUNK_WORDS: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankSelect {
+    bits: BitVec<u64, Lsb0>,
+    // Per block, the ones before the block and the packed ones before each of its words
+    counts: Vec<(u64, u64)>,
+    // Block holding the (i * SELECT_SAMPLE)-th one and zero
+    ones_samples: Vec<u32>,
+    zeros_samples: Vec<u32>,
+    // Per sample, the positions of the ones (or zeros) of a sparse run
+    ones_sparse: Vec<Option<Box<[u64]>>>,
+    zeros_sparse: Vec<Option<Box<[u64]>>>,
+    ones: usize,
+}
+
+impl From<BitVec<u64, Lsb0>> for RankSelect {
+    fn from(bits: BitVec<u64, Lsb0>) -> Self {
+        Self::new(bits)
+    }
+}
+
+impl FromIterator<bool> for RankSelect {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl RankSelect {
+    pub fn new(mut bits: BitVec<u64, Lsb0>) -> Self {
+        // Ranks popcount whole words, the tail past the last bit must be clear
+        bits.set_uninitialized(false);
+        let words = bits.as_raw_slice();
+        let block_count = words.len().div_ceil(WORDS_PER_BLOCK);
+        assert!(
+            block_count <= u32::MAX as usize,
+            "Bit vector too long for select samples"
+        );
+
+        let mut counts = Vec::with_capacity(block_count);
+        let mut ones_samples = Vec::new();
+        let mut zeros_samples = Vec::new();
+        let mut ones = 0;
+        for (b, block) in words.chunks(WORDS_PER_BLOCK).enumerate() {
+            let zeros = b * BLOCK_BITS - ones;
+            let mut relative = 0;
+            let mut in_block = 0;
+            for (w, word) in block.iter().enumerate() {
+                if w > 0 {
+                    relative |= (in_block as u64) << (9 * (w - 1));
+                }
+                in_block += word.count_ones() as usize;
+            }
+            // Fill the counts of words past the end, so every block reads the same
+            for w in block.len().max(1)..WORDS_PER_BLOCK {
+                relative |= (in_block as u64) << (9 * (w - 1));
+            }
+            counts.push((ones as u64, relative));
+
+            let block_zeros = (block.len() * 64).min(bits.len() - b * BLOCK_BITS) - in_block;
+            while ones_samples.len() * SELECT_SAMPLE < ones + in_block {
+                ones_samples.push(b as u32);
+            }
+            while zeros_samples.len() * SELECT_SAMPLE < zeros + block_zeros {
+                zeros_samples.push(b as u32);
+            }
+            ones += in_block;
+        }
+
+        let mut rs = Self {
+            bits,
+            counts,
+            ones_samples,
+            zeros_samples,
+            ones_sparse: Vec::new(),
+            zeros_sparse: Vec::new(),
+            ones,
+        };
+        let ones_sparse = (0..rs.ones_samples.len())
+            .map(|s| rs.sparse_run(s, rs.ones, |n| rs.select1(n), true))
+            .collect();
+        let zeros_sparse = (0..rs.zeros_samples.len())
+            .map(|s| rs.sparse_run(s, rs.count_zeros(), |n| rs.select0(n), false))
+            .collect();
+        rs.ones_sparse = ones_sparse;
+        rs.zeros_sparse = zeros_sparse;
+        rs
+    }
+
+    // Positions of the run of ones (or zeros) starting at a sample, when it spans over SPARSE_SPAN bits
+    fn sparse_run(
+        &self,
+        sample: usize,
+        total: usize,
+        select: impl Fn(usize) -> Option<usize>,
+        ones: bool,
+    ) -> Option<Box<[u64]>> {
+        let first = sample * SELECT_SAMPLE;
+        let count = SELECT_SAMPLE.min(total - first);
+        let start = select(first).unwrap();
+        let end = select(first + SELECT_SAMPLE).unwrap_or(self.len());
+        if end - start <= SPARSE_SPAN {
+            return None;
+        }
+        let run = &self.bits[start..end];
+        let positions: Box<[u64]> = if ones {
+            run.iter_ones()
+                .take(count)
+                .map(|p| (start + p) as u64)
+                .collect()
+        } else {
+            run.iter_zeros()
+                .take(count)
+                .map(|p| (start + p) as u64)
+                .collect()
+        };
+        Some(positions)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    #[inline]
+    pub fn bits(&self) -> &BitSlice<u64, Lsb0> {
+        &self.bits
+    }
+
+    // Underlying words, bit i being bit i % 64 of word i / 64
+    #[inline]
+    pub fn words(&self) -> &[u64] {
+        self.bits.as_raw_slice()
+    }
+
+    pub fn into_inner(self) -> BitVec<u64, Lsb0> {
+        self.bits
+    }
+
+    #[inline]
+    pub fn get(&self, i: usize) -> bool {
+        self.bits[i]
+    }
+
+    #[inline]
+    pub fn count_ones(&self) -> usize {
+        self.ones
+    }
+
+    #[inline]
+    pub fn count_zeros(&self) -> usize {
+        self.len() - self.ones
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.bits.as_raw_slice().len() * 8
+            + self.counts.len() * 16
+            + (self.ones_samples.len() + self.zeros_samples.len()) * 4
+            + self
+                .ones_sparse
+                .iter()
+                .chain(self.zeros_sparse.iter())
+                .map(|run| run.as_ref().map_or(0, |p| p.len() * 8) + 16)
+                .sum::<usize>()
+    }
+
+    // Ones before the block, and before word w of the block
+    #[inline]
+    fn ones_before(&self, block: usize, w: usize) -> usize {
+        let (absolute, relative) = self.counts[block];
+        let relative = if w == 0 {
+            0
+        } else {
+            (relative >> (9 * (w - 1))) & 0x1ff
+        };
+        (absolute + relative) as usize
+    }
+
+    // Number of ones in [0, i)
+    #[inline]
+    pub fn rank1(&self, i: usize) -> usize {
+        assert!(i <= self.len(), "Rank past the end of the bit vector");
+        if i == self.len() {
+            return self.ones;
+        }
+        let word = i / 64;
+        let before = self.ones_before(word / WORDS_PER_BLOCK, word % WORDS_PER_BLOCK);
+        let mask = (1u64 << (i % 64)) - 1;
+        before + (self.bits.as_raw_slice()[word] & mask).count_ones() as usize
+    }
+
+    // Number of zeros in [0, i)
+    #[inline]
+    pub fn rank0(&self, i: usize) -> usize {
+        i - self.rank1(i)
+    }
+
+    // Position of the n-th one, counting from zero
+    pub fn select1(&self, n: usize) -> Option<usize> {
+        if n >= self.ones {
+            return None;
+        }
+        Some(self.select(
+            n,
+            &self.ones_samples,
+            &self.ones_sparse,
+            |rs, b, w| rs.ones_before(b, w),
+            |x| x,
+        ))
+    }
+
+    // Position of the n-th zero, counting from zero
+    pub fn select0(&self, n: usize) -> Option<usize> {
+        if n >= self.count_zeros() {
+            return None;
+        }
+        Some(self.select(
+            n,
+            &self.zeros_samples,
+            &self.zeros_sparse,
+            |rs, b, w| b * BLOCK_BITS + w * 64 - rs.ones_before(b, w),
+            |x| !x,
+        ))
+    }
+
+    #[inline]
+    fn select(
+        &self,
+        n: usize,
+        samples: &[u32],
+        sparse: &[Option<Box<[u64]>>],
+        before: impl Fn(&Self, usize, usize) -> usize,
+        word: impl Fn(u64) -> u64,
+    ) -> usize {
+        let sample = n / SELECT_SAMPLE;
+        if let Some(Some(positions)) = sparse.get(sample) {
+            return positions[n % SELECT_SAMPLE] as usize;
+        }
+        // Last block starting with at most n bits of the kind before it
+        let mut lo = samples[sample] as usize;
+        let mut hi = samples
+            .get(sample + 1)
+            .map_or(self.counts.len(), |&b| b as usize + 1);
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if before(self, mid, 0) <= n {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let block = lo;
+
+        let mut w = 0;
+        while w + 1 < WORDS_PER_BLOCK && before(self, block, w + 1) <= n {
+            w += 1;
+        }
+        let index = block * WORDS_PER_BLOCK + w;
+        let rank = (n - before(self, block, w)) as u32;
+        index * 64 + select_in_word(word(self.bits.as_raw_slice()[index]), rank) as usize
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+        for word in self.bits.as_raw_slice() {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        if header[0..8] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a Camilla bit vector",
+            ));
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported format version {}", version),
+            ));
+        }
+        let len = usize::try_from(u64::from_le_bytes(header[12..20].try_into().unwrap()))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bit vector too long"))?;
+
+        // Grow with the data actually read, a truncated stream fails before a large allocation
+        let word_count = len.div_ceil(64);
+        let mut words = Vec::with_capacity(word_count.min(READ_CHUNK_WORDS));
+        let mut bytes = vec![0; word_count.min(READ_CHUNK_WORDS) * 8];
+        while words.len() < word_count {
+            let chunk = (word_count - words.len()).min(READ_CHUNK_WORDS) * 8;
+            reader.read_exact(&mut bytes[..chunk])?;
+            words.extend(
+                bytes[..chunk]
+                    .chunks_exact(8)
+                    .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())),
+            );
+        }
+        let mut bits = BitVec::from_vec(words);
+        bits.truncate(len);
+        Ok(Self::new(bits))
+    }
+}
+
+// Position of the r-th one of x, which must hold more than r ones
+#[inline]
+fn select_in_word(mut x: u64, mut r: u32) -> u32 {
+    let mut pos = 0;
+    for shift in [32, 16, 8, 4, 2, 1] {
+        let low = (x & ((1u64 << shift) - 1)).count_ones();
+        if r >= low {
+            r -= low;
+            x >>= shift;
+            pos += shift;
+        }
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_bits(len: usize, density: f64, rng: &mut StdRng) -> BitVec<u64, Lsb0> {
+        (0..len).map(|_| rng.gen_bool(density)).collect()
+    }
+
+    // Compare with naive rank and select, checking every `step`-th position and zero
+    fn check(bits: BitVec<u64, Lsb0>, step: usize) {
+        let rs = RankSelect::new(bits.clone());
+        let ones: Vec<usize> = bits.iter_ones().collect();
+        let zeros: Vec<usize> = bits.iter_zeros().collect();
+        for i in (0..=bits.len()).step_by(step) {
+            let rank = ones.partition_point(|&p| p < i);
+            assert_eq!(rs.rank1(i), rank, "rank1({})", i);
+            assert_eq!(rs.rank0(i), i - rank, "rank0({})", i);
+        }
+        assert_eq!(rs.rank1(bits.len()), ones.len());
+        assert_eq!(rs.count_ones(), ones.len());
+        assert_eq!(rs.count_zeros(), zeros.len());
+        for (n, &pos) in ones.iter().enumerate() {
+            assert_eq!(rs.select1(n), Some(pos), "select1({})", n);
+        }
+        for (n, &pos) in zeros.iter().enumerate().step_by(step) {
+            assert_eq!(rs.select0(n), Some(pos), "select0({})", n);
+        }
+        assert_eq!(rs.select1(ones.len()), None);
+        assert_eq!(rs.select0(zeros.len()), None);
+    }
+
+    #[test]
+    fn matches_naive() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for len in [1, 63, 64, 65, 511, 512, 513, 100_000] {
+            for density in [0.01, 0.5, 0.99] {
+                check(random_bits(len, density, &mut rng), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_vectors() {
+        check(BitVec::new(), 1);
+        for len in [1, 64, 5000, 70_000] {
+            check(BitVec::repeat(true, len), 1);
+            check(BitVec::repeat(false, len), 1);
+        }
+    }
+
+    #[test]
+    fn sparse_runs() {
+        // Runs of SELECT_SAMPLE ones spanning more than SPARSE_SPAN bits
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut bits = BitVec::repeat(false, 3 * SPARSE_SPAN);
+        let mut pos = 0;
+        while pos < bits.len() {
+            bits.set(pos, true);
+            pos += rng.gen_range(500..1500);
+        }
+        let rs = RankSelect::new(bits.clone());
+        assert!(rs.ones_sparse.iter().any(Option::is_some));
+        check(bits, 97);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for len in [0, 1, 64, 1000] {
+            let rs = RankSelect::new(random_bits(len, 0.3, &mut rng));
+            let mut bytes = Vec::new();
+            rs.write_to(&mut bytes).unwrap();
+            assert_eq!(bytes.len(), HEADER_LEN + len.div_ceil(64) * 8);
+            assert_eq!(RankSelect::read_from(&bytes[..]).unwrap(), rs);
+            assert!(RankSelect::read_from(&bytes[..bytes.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupt_length() {
+        let mut bytes = Vec::new();
+        RankSelect::new(BitVec::repeat(true, 100))
+            .write_to(&mut bytes)
+            .unwrap();
+        bytes[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(RankSelect::read_from(&bytes[..]).is_err());
+    }
+}
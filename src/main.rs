@@ -1,8 +1,7 @@
 use libcamilla::filters::blocks::blanket::BlanketBBFBlock;
 use libcamilla::filters::bloom::BBFilter;
-use libcamilla::structures::sequence::complement::{Complement, Forward, Identity, Reverse};
-use libcamilla::structures::sequence::{nucleotide::Nucleotide, packed::PackedSeq, read::ReadSeq};
-use std::{hint::black_box, mem::size_of};
+use libcamilla::structures::sequence::read::ReadSeq;
+use std::hint::black_box;
 
 fn main() {
     let mut seq = ReadSeq {
@@ -19,13 +18,13 @@ fn main() {
     // println!("{}", seq.sequence);
 
     let bb = black_box(seq);
-    let mut res = bb.pack::<u8>();
+    let res = bb.pack::<u8>();
 
-    let filter = BBFilter::<BlanketBBFBlock>::new(100, 24);
+    let filter = BBFilter::<BlanketBBFBlock>::new(3, 100, 24, 2);
 
-    filter.insert_kmers(&res, 3);
+    filter.insert_kmers(&res);
 
-    println!("{}", filter.contains_kmer(res.slice(1, 5)));
+    println!("{}", filter.contains_kmer(res.slice(1, 3)));
     println!(
         "{} {}",
         res.slice(res.len() - 3, 3),